@@ -0,0 +1,108 @@
+//! A compact binary encoding of the grid squares
+//! Each square is written as a variable length integer of its layers, and
+//! runs of empty squares are written as a zero followed by the run length.
+//! This is used to store states on disk and to hash levels in a stable way
+
+use std::io;
+
+use crate::level::Level;
+use crate::square::Square;
+
+/// Appends a variable length integer, 7 bits per byte with the high bit
+/// set when more bytes follow
+pub fn write_varint(bytes: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        bytes.push((value as u8 & 0x7f) | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+/// Reads a variable length integer at the given offset, moving the offset after it
+pub fn read_varint(bytes: &[u8], offset: &mut usize) -> io::Result<u64> {
+    let mut value = 0;
+    for shift in (0..64).step_by(7) {
+        let byte = *bytes
+            .get(*offset)
+            .ok_or_else(|| invalid("truncated integer"))?;
+        *offset += 1;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(invalid("integer too long"))
+}
+
+/// Encodes a list of squares
+pub fn encode_squares(squares: &[Square]) -> Vec<u8> {
+    let mut bytes = vec![];
+    let mut empty_run = 0;
+    for square in squares {
+        if square.bits() == 0 {
+            empty_run += 1;
+            continue;
+        }
+        if empty_run > 0 {
+            bytes.push(0);
+            write_varint(&mut bytes, empty_run);
+            empty_run = 0;
+        }
+        write_varint(&mut bytes, u64::from(square.bits()));
+    }
+    if empty_run > 0 {
+        bytes.push(0);
+        write_varint(&mut bytes, empty_run);
+    }
+    bytes
+}
+
+/// Decodes the given number of squares
+pub fn decode_squares(bytes: &[u8], len: usize) -> io::Result<Vec<Square>> {
+    let mut squares = Vec::with_capacity(len);
+    let mut offset = 0;
+    while offset < bytes.len() {
+        match read_varint(bytes, &mut offset)? {
+            0 => {
+                let run = read_varint(bytes, &mut offset)? as usize;
+                squares.resize(squares.len() + run, Square::default());
+            }
+            value => squares.push(Square::from_bits(value as u32)),
+        }
+    }
+    if squares.len() == len {
+        Ok(squares)
+    } else {
+        Err(invalid("wrong number of squares"))
+    }
+}
+
+/// A hash of the level dimensions and squares, stable between runs and machines
+/// (64 bits FNV-1a of the encoding)
+pub fn level_hash(level: &Level) -> u64 {
+    let mut bytes = vec![];
+    write_varint(&mut bytes, level.grid.width() as u64);
+    write_varint(&mut bytes, level.grid.height() as u64);
+    bytes.extend(encode_squares(level.grid.squares()));
+    fnv1a(&bytes)
+}
+
+/// 64 bits FNV-1a hash
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+pub fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+#[test]
+fn squares_encoding() {
+    let level = crate::levels_list::LEVELS_LIST[0].clone();
+    let squares = level.grid.squares();
+    let bytes = encode_squares(squares);
+    assert!(bytes.len() < squares.len());
+    assert_eq!(decode_squares(&bytes, squares.len()).unwrap(), squares);
+}
@@ -33,8 +33,14 @@ impl Grid {
 }
 
 impl Grid {
+    pub fn width(&self) -> usize {
+        self.width
+    }
+    pub fn height(&self) -> usize {
+        self.height
+    }
     pub fn left(&self, elem: usize) -> Option<usize> {
-        if elem % self.width > 0 {
+        if elem % self.width != 0 {
             Some(elem - 1)
         } else {
             None
@@ -62,15 +68,22 @@ impl Grid {
         }
     }
     /// Apply a move to the given element, if the move is invalid, returns None
+    /// Waiting keeps the element in place
     pub fn apply_move(&self, elem: usize, m: Move) -> Option<usize> {
         match m {
             Move::Left => self.left(elem),
             Move::Right => self.right(elem),
             Move::Up => self.up(elem),
             Move::Down => self.down(elem),
+            Move::Wait => Some(elem),
         }
     }
 
+    /// Returns all the squares of the grid in 1D coordinates
+    pub fn squares(&self) -> &[Square] {
+        &self.elems
+    }
+
     /// Converts a 2D Position into an index
     pub fn index(&self, pos: Position) -> usize {
         pos.0 + pos.1 * self.width
@@ -1,20 +1,35 @@
 //! A module interpreting a move sequence in a given level
+//!
+//! A turn is resolved in fixed phases, in the same order as the original game:
+//! 1. the units tagged YOU move in the given direction, pushing what they can
+//! 2. the units tagged MOVE move (no such property exists yet, skipped)
+//! 3. the units tagged SHIFT move what they stand on (no such property exists yet, skipped)
+//! 4. the rules are parsed again from the text on the grid
+//! 5. the transformations such as `BABA IS ROCK` are applied
+//! 6. the units tagged YOU sharing a square with a unit tagged DEFEAT are destroyed
+//! 7. the end of the game is checked on the final grid
+//!
+//! A square holds at most one unit of each kind: a unit moved, pushed or transformed
+//! onto a square already holding a unit of its kind is merged with it, both
+//! becoming a single unit from then on
 
 use crate::level::*;
 use crate::square::*;
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum Move {
     Left,
     Right,
     Up,
     Down,
+    Wait,
 }
 
 pub const LEFT: Move = Move::Left;
 pub const RIGHT: Move = Move::Right;
 pub const UP: Move = Move::Up;
 pub const DOWN: Move = Move::Down;
+pub const WAIT: Move = Move::Wait;
 
 /// State of the game after the move, still playing, win,
 /// defeat or stuck. None means ongoing
@@ -40,102 +55,280 @@ fn update_game_state(result: GameState, game_state: GameState) -> GameState {
     }
 }
 
-/// Checks if a winning or loosing condition is triggerd on a given entity
-fn check_end(old_level: &Level, pos: usize, entity: Entity) -> GameState {
-    // If the entity is marked as you
-    if old_level.rules[entity][usize::from(TYOU)] {
-        // Win has a higher priority
-        if old_level
-            .rules
-            .square_has_property(old_level.grid[pos], TWIN)
-        {
-            WIN
-        } else {
-            ONGOING
-        }
-    } else {
-        ONGOING
+/// Sorting key to resolve the units in front first, so that a line of
+/// units moving in the same direction moves as a whole
+fn front_first(pos: Position, m: Move) -> isize {
+    let (x, y) = (pos.0 as isize, pos.1 as isize);
+    match m {
+        Move::Left => x,
+        Move::Right => -x,
+        Move::Up => y,
+        Move::Down => -y,
+        Move::Wait => 0,
     }
 }
 
 impl Level {
-    /// Apply the given move to all the entities
-    /// that are tagged YOU
+    /// Plays a whole turn where all the entities tagged YOU are moved
+    /// in the given direction, see the module documentation for the phases order
     pub fn apply_move(&mut self, m: Move) -> GameState {
-        // finding you(s)
-        let you_entities: Vec<&Entity> = ENTITIES
-            .iter()
-            .filter(|&&e| self.rules[e][usize::from(TYOU)])
-            .collect();
-        let old_level = self.clone();
-
-        // Folding the game state result for each entity type that are tagged you
-        you_entities.iter().fold(ONGOING, |game_state, &&you| {
-            // For each world entity we apply the move
-            let you_result = old_level.grid[LayeredSquare::from(you)].iter().fold(
-                ONGOING,
-                |game_state, &elem| {
-                    update_game_state(self.move_entity(m, (elem, you), &old_level).0, game_state)
-                },
-            );
-            update_game_state(you_result, game_state)
-        })
+        self.apply_move_recording(m, &mut vec![])
     }
 
-    /// Main physics function applying all the constraints to a moving entity
-    /// Returns if the game ends and wether the entity could move
-    fn move_entity(
+    /// Plays a whole turn like `apply_move`, adding the position and the previous
+    /// content of each square changed by a phase, a square can be added several times
+    pub fn apply_move_recording(
         &mut self,
         m: Move,
-        entity: (usize, Entity),
-        old_level: &Level,
-    ) -> (GameState, bool) {
-        let (pos, entity) = entity;
-        // We check that the move ends inside the grid boundary
-        if let Some(dest) = self.grid.apply_move(pos, m) {
-            // If there is a layer tagged STOP we cannot go
-            if old_level
-                .rules
-                .square_has_property(old_level.grid[pos], TSTOP)
-            {
-                return (ONGOING, false);
+        changes: &mut Vec<(usize, Square)>,
+    ) -> GameState {
+        self.move_you_units(m, changes);
+        self.update_rules();
+        self.apply_transformations(changes);
+        self.destroy_defeated_units(changes);
+        self.end_state()
+    }
+
+    pub fn apply_move_sequence(&mut self, ms: Vec<Move>) -> GameState {
+        ms.iter().fold(ONGOING, |game_state, &m| {
+            update_game_state(self.apply_move(m), game_state)
+        })
+    }
+
+    /// Returns all the units having the given property as (layer, position in 1D coordinates)
+    pub fn units_with_property(&self, property: Text) -> Vec<(LayeredSquare, usize)> {
+        (0..LAYERED_SQUARES_NUMBER)
+            .map(LayeredSquare::from)
+            .filter(|&layer| self.rules[Entity::from(layer)][usize::from(property)])
+            .flat_map(|layer| self.grid[layer].iter().map(move |&pos| (layer, pos)))
+            .collect()
+    }
+
+    /// First phase: moves all the units tagged YOU, they stay in place when waiting
+    fn move_you_units(&mut self, m: Move, changes: &mut Vec<(usize, Square)>) {
+        if m == WAIT {
+            return;
+        }
+        let mut movers = self.units_with_property(TYOU);
+        let width = self.grid.width();
+        movers.sort_by_key(|&(_, pos)| front_first((pos % width, pos / width), m));
+
+        for (layer, pos) in movers {
+            // The unit may have been pushed away by a previous one
+            if !self.grid[pos].has_layer(layer) || !self.can_move(pos, m) {
+                continue;
             }
+            let dest = self
+                .grid
+                .apply_move(pos, m)
+                .expect("A movable unit stays inside the grid");
+            self.push(dest, m, changes);
+            self.move_internal(layer, pos, dest, changes);
+        }
+    }
 
-            // If there is a layer tagged PUSH we try to recursively push on all the line
-            for layer in old_level.grid[dest]
-                .into_iter()
-                .filter(|&layer| old_level.rules[Entity::from(layer)][usize::from(TPUSH)])
-            {
-                let result = self.move_entity(m, (dest, Entity::from(layer)), old_level);
-                // If the push succeeds we can go there and update the result
-                if result.1 {
-                    self.move_internal(LayeredSquare::from(entity), pos, dest);
-                    return (
-                        update_game_state(check_end(old_level, dest, entity), result.0),
-                        true,
-                    );
+    /// Main physics function checking if a unit on the given square can move,
+    /// a unit is blocked by the grid border, by STOP units and by PUSH units that cannot move
+    fn can_move(&self, pos: usize, m: Move) -> bool {
+        if let Some(dest) = self.grid.apply_move(pos, m) {
+            self.grid[dest].into_iter().all(|layer| {
+                let entity = Entity::from(layer);
+                if self.rules[entity][usize::from(TPUSH)] {
+                    self.can_move(dest, m)
+                } else {
+                    !self.rules[entity][usize::from(TSTOP)]
                 }
-            }
+            })
+        } else {
+            false
+        }
+    }
 
-            // TODO other rules
+    /// Recursively pushes all the units tagged PUSH on the given square,
+    /// the push is expected to be valid
+    fn push(&mut self, pos: usize, m: Move, changes: &mut Vec<(usize, Square)>) {
+        let pushed: Vec<LayeredSquare> = self.grid[pos]
+            .into_iter()
+            .filter(|&layer| self.rules[Entity::from(layer)][usize::from(TPUSH)])
+            .collect();
+        if pushed.is_empty() {
+            return;
+        }
+        let dest = self
+            .grid
+            .apply_move(pos, m)
+            .expect("A pushed unit stays inside the grid");
+        self.push(dest, m, changes);
+        for layer in pushed {
+            self.move_internal(layer, pos, dest, changes);
+        }
+    }
 
-            // If there is nothing special we just move
-            self.move_internal(LayeredSquare::from(entity), pos, dest);
-            (check_end(old_level, dest, entity), true)
-        } else {
-            (ONGOING, false)
+    /// Fifth phase: transforms the entities according to `ENTITY IS ENTITY` rules,
+    /// `ENTITY IS ENTITY` on itself prevents any transformation.
+    /// Every unit is transformed at once from the grid before the phase, so that
+    /// `BABA IS ROCK` and `ROCK IS BABA` swap them and a unit changes once per turn.
+    /// Transformations into text or empty are not supported
+    fn apply_transformations(&mut self, changes: &mut Vec<(usize, Square)>) {
+        // (layer, position, targets)
+        let mut transformed = vec![];
+        for &entity in ENTITIES
+            .iter()
+            .filter(|&&e| e != Entity::EMPTY && e != Entity::TEXT)
+        {
+            if self.rules[entity][usize::from(Text::from(entity))] {
+                continue;
+            }
+            let targets: Vec<Entity> = ENTITIES
+                .iter()
+                .cloned()
+                .filter(|&t| t != entity && t != Entity::EMPTY && t != Entity::TEXT)
+                .filter(|&t| self.rules[entity][usize::from(Text::from(t))])
+                .collect();
+            if targets.is_empty() {
+                continue;
+            }
+            let layer = LayeredSquare::from(entity);
+            for &pos in &self.grid[layer] {
+                transformed.push((layer, pos, targets.clone()));
+            }
+        }
+        for &(layer, pos, _) in &transformed {
+            changes.push((pos, self.grid[pos]));
+            self.remove_layer_internal(layer, pos);
+        }
+        for (_, pos, targets) in transformed {
+            for target in targets {
+                self.add_layer_internal(LayeredSquare::from(target), pos);
+            }
         }
     }
 
-    pub fn apply_move_sequence(&mut self, ms: Vec<Move>) -> GameState {
-        ms.iter().fold(ONGOING, |game_state, &m| {
-            update_game_state(self.apply_move(m), game_state)
-        })
+    /// Sixth phase: destroys the units tagged YOU standing on a unit tagged DEFEAT
+    fn destroy_defeated_units(&mut self, changes: &mut Vec<(usize, Square)>) {
+        for (layer, pos) in self.units_with_property(TYOU) {
+            if self.rules.square_has_property(self.grid[pos], TDEFEAT) {
+                changes.push((pos, self.grid[pos]));
+                self.remove_layer_internal(layer, pos);
+            }
+        }
     }
 
-    /// Internaly applies a move without checks, updating the according layers and rules
-    fn move_internal(&mut self, layer: LayeredSquare, start: usize, dest: usize) {
+    /// Last phase: evaluates the end of the turn on the final grid and rules.
+    /// The game is won when a unit tagged YOU shares a square with a unit tagged WIN,
+    /// this unit can be the same one. Having no unit tagged YOU anymore loses the game
+    pub fn end_state(&self) -> GameState {
+        let yous = self.units_with_property(TYOU);
+        if yous.is_empty() {
+            DEFEAT
+        } else if yous
+            .iter()
+            .any(|&(_, pos)| self.rules.square_has_property(self.grid[pos], TWIN))
+        {
+            WIN
+        } else {
+            ONGOING
+        }
+    }
+
+    /// Internaly applies a move without checks, updating the according layers and rules.
+    /// The unit is merged with a unit of the same kind on the destination
+    fn move_internal(
+        &mut self,
+        layer: LayeredSquare,
+        start: usize,
+        dest: usize,
+        changes: &mut Vec<(usize, Square)>,
+    ) {
+        changes.push((start, self.grid[start]));
+        changes.push((dest, self.grid[dest]));
         self.remove_layer_internal(layer, start);
         self.add_layer_internal(layer, dest);
     }
 }
+
+#[test]
+fn stop_blocks_you() {
+    let mut level = crate::levels_list::LEVELS_LIST[0].clone();
+    let moves = crate::notation::parse_moves("U3").expect("The notation is valid");
+    assert_eq!(level.apply_move_sequence(moves), ONGOING);
+    let baba = level.grid[LayeredSquare::from(Entity::BABA)].clone();
+    assert_eq!(baba, vec![level.grid.index((3, 4))]);
+}
+
+#[test]
+fn rules_are_parsed_after_moving() {
+    let mut level = crate::levels_list::LEVELS_LIST[0].clone();
+    let moves = crate::notation::parse_moves("L2U3R").expect("The notation is valid");
+    assert_eq!(level.apply_move_sequence(moves), ONGOING);
+    assert!(level.rules[Entity::BABA][usize::from(TYOU)]);
+    // Pushing BABA out of BABA IS YOU
+    assert_eq!(level.apply_move(UP), DEFEAT);
+    assert!(!level.rules[Entity::BABA][usize::from(TYOU)]);
+}
+
+#[test]
+fn you_and_win_wins_at_end_of_turn() {
+    let mut level = Level::new(6, 3);
+    level.add_rule(&[TBABA, TIS, TYOU, TAND, TWIN], (0, 0), HORIZONTAL);
+    level.add_square(Entity::BABA.into(), (0, 2));
+    assert!(level.rules[Entity::BABA][usize::from(TWIN)]);
+    assert_eq!(level.end_state(), WIN);
+    assert_eq!(level.apply_move(RIGHT), WIN);
+}
+
+#[test]
+fn rule_formed_during_the_turn_wins() {
+    let mut level = Level::new(3, 3);
+    level.add_rule(&[TBABA, TIS, TYOU], (0, 0), HORIZONTAL);
+    level.add_square(TIS.into(), (0, 1));
+    level.add_square(TWIN.into(), (1, 2));
+    level.add_square(Entity::BABA.into(), (2, 2));
+    assert_eq!(level.end_state(), ONGOING);
+    // Pushing WIN under BABA IS
+    assert_eq!(level.apply_move(LEFT), WIN);
+}
+
+#[test]
+fn transformations_apply_at_once() {
+    let mut level = Level::new(4, 4);
+    level.add_rule(&[TBABA, TIS, TROCK], (0, 0), HORIZONTAL);
+    level.add_rule(&[TROCK, TIS, TBABA], (0, 1), HORIZONTAL);
+    level.add_square(Entity::BABA.into(), (0, 3));
+    level.add_square(Entity::ROCK.into(), (3, 3));
+    level.apply_move(WAIT);
+    let baba = &level.grid[LayeredSquare::from(Entity::BABA)];
+    assert_eq!(baba, &vec![level.grid.index((3, 3))]);
+    let rock = &level.grid[LayeredSquare::from(Entity::ROCK)];
+    assert_eq!(rock, &vec![level.grid.index((0, 3))]);
+
+    // A chain of transformations takes one turn per step
+    let mut level = Level::new(4, 4);
+    level.add_rule(&[TBABA, TIS, TFLAG], (0, 0), HORIZONTAL);
+    level.add_rule(&[TFLAG, TIS, TWALL], (0, 1), HORIZONTAL);
+    level.add_square(Entity::BABA.into(), (0, 3));
+    let pos = level.grid.index((0, 3));
+    level.apply_move(WAIT);
+    assert_eq!(level.grid[LayeredSquare::from(Entity::FLAG)], vec![pos]);
+    assert!(level.grid[LayeredSquare::from(Entity::WALL)].is_empty());
+    level.apply_move(WAIT);
+    assert!(level.grid[LayeredSquare::from(Entity::FLAG)].is_empty());
+    assert_eq!(level.grid[LayeredSquare::from(Entity::WALL)], vec![pos]);
+}
+
+#[test]
+fn units_of_the_same_kind_merge() {
+    let mut level = Level::new(4, 4);
+    level.add_rule(&[TBABA, TIS, TYOU], (0, 0), HORIZONTAL);
+    level.add_rule(&[TWALL, TIS, TSTOP], (0, 1), HORIZONTAL);
+    level.add_square(Entity::BABA.into(), (0, 3));
+    level.add_square(Entity::BABA.into(), (1, 3));
+    level.add_square(Entity::WALL.into(), (2, 3));
+    // The first BABA is blocked, the second one walks onto it
+    assert_eq!(level.apply_move(RIGHT), ONGOING);
+    let pos = level.grid.index((1, 3));
+    assert_eq!(level.grid[LayeredSquare::from(Entity::BABA)], vec![pos]);
+    assert_eq!(level.units_with_property(TYOU).len(), 1);
+    assert!(!level.add_layer_internal(Entity::BABA.into(), pos));
+    assert!(level.add_layer_internal(Entity::BABA.into(), level.grid.index((0, 3))));
+}
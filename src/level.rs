@@ -1,12 +1,11 @@
 //! A module for a whole level
 //! This is mostly a wrapper around a grid and rule manager
 
-use std::convert::TryFrom;
-
 use crate::grid::*;
 use crate::rules::*;
 use crate::square::*;
 
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum Direction {
     VERTICAL,
@@ -38,12 +37,18 @@ impl Level {
         self.add_layer_internal(layer, self.grid.index(pos));
     }
 
-    /// Adds a layered square to the specified square in 1D coordinates
-    pub fn add_layer_internal(&mut self, layer: LayeredSquare, pos: usize) {
+    /// Adds a layered square to the specified square in 1D coordinates.
+    /// A square holds at most one unit of each kind, a unit added on a square
+    /// already holding one of its kind is merged with it and false is returned
+    pub fn add_layer_internal(&mut self, layer: LayeredSquare, pos: usize) -> bool {
+        if self.grid[pos].has_layer(layer) {
+            return false;
+        }
         // Adding to the grid
         self.grid[pos].add_layer(layer);
         // Adding to the tracking
         self.grid[layer].push(pos);
+        true
     }
 
     /// Removes a layer from the grid and tracking (position in 1D coordinates)
@@ -53,6 +58,22 @@ impl Level {
         self.grid[layer].swap_remove(remove_index);
     }
 
+    /// Replaces the content of a square (position in 1D coordinates), updating the tracking
+    pub fn set_square(&mut self, pos: usize, square: Square) {
+        let old = self.grid[pos];
+        for layer in old.into_iter().filter(|&l| !square.has_layer(l)) {
+            self.remove_layer_internal(layer, pos);
+        }
+        for layer in square.into_iter().filter(|&l| !old.has_layer(l)) {
+            self.add_layer_internal(layer, pos);
+        }
+    }
+
+    /// Parses the rules again from the text on the grid
+    pub fn update_rules(&mut self) {
+        self.rules = RuleManager::parse(&self.grid);
+    }
+
     pub fn add_square_line(
         &mut self,
         square: LayeredSquare,
@@ -68,18 +89,14 @@ impl Level {
     }
 
     /// Adds a rule to the current level
-    pub fn add_rule(&mut self, rule: [Text; 3], mut pos: Position, dir: Direction) {
+    pub fn add_rule(&mut self, rule: &[Text], mut pos: Position, dir: Direction) {
         let offset = Position::from(dir);
         // Placing the squares
-        for &text in &rule {
+        for &text in rule {
             self.add_square(LayeredSquare::from(text), pos);
             pos = offset_pos(pos, offset);
         }
-        // Creating the rule
-        // TODO correct parsing
-        let entity =
-            Entity::try_from(rule[0]).expect("The first element of a rule should be an entity");
-        self.rules[entity][usize::from(rule[2])] = true;
+        self.update_rules();
     }
 }
 
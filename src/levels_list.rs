@@ -23,10 +23,10 @@ fn level_1() -> Level {
     level.add_square(Entity::BABA.into(), (3, 5));
     level.add_square(Entity::FLAG.into(), (11, 5));
     // Rules
-    level.add_rule([TBABA, TIS, TYOU], (2, 1), HORIZONTAL);
-    level.add_rule([TFLAG, TIS, TWIN], (10, 1), HORIZONTAL);
-    level.add_rule([TWALL, TIS, TSTOP], (2, 9), HORIZONTAL);
-    level.add_rule([TROCK, TIS, TPUSH], (10, 9), HORIZONTAL);
+    level.add_rule(&[TBABA, TIS, TYOU], (2, 1), HORIZONTAL);
+    level.add_rule(&[TFLAG, TIS, TWIN], (10, 1), HORIZONTAL);
+    level.add_rule(&[TWALL, TIS, TSTOP], (2, 9), HORIZONTAL);
+    level.add_rule(&[TROCK, TIS, TPUSH], (10, 9), HORIZONTAL);
 
     level
 }
@@ -0,0 +1,22 @@
+//! A Baba Is You interpreter and solver
+//!
+//! The turn phases are described in the `interpreter` module
+
+#[macro_use]
+extern crate enum_primitive;
+#[macro_use]
+extern crate lazy_static;
+
+pub mod encoding;
+pub mod grid;
+pub mod interpreter;
+pub mod level;
+pub mod levels_list;
+pub mod notation;
+pub mod play;
+pub mod render;
+pub mod rules;
+pub mod session;
+pub mod solution;
+pub mod solver;
+pub mod square;
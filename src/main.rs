@@ -1,34 +1,694 @@
-#[macro_use]
-extern crate enum_primitive;
-#[macro_use]
-extern crate lazy_static;
+use std::collections::HashMap;
+use std::env;
+use std::io;
+use std::process;
+use std::sync::Arc;
+use std::time::Duration;
 
-mod grid;
-mod interpreter;
-mod level;
-mod levels_list;
-mod rules;
-mod square;
+use baba_solver::level::Level;
+use baba_solver::levels_list::*;
+use baba_solver::notation::*;
+use baba_solver::play::play;
+use baba_solver::render::Renderer;
+use baba_solver::solution::*;
+use baba_solver::solver::beam::*;
+use baba_solver::solver::bfs::Bfs;
+use baba_solver::solver::bidirectional::Bidirectional;
+use baba_solver::solver::certificate::*;
+use baba_solver::solver::checkpoint::*;
+use baba_solver::solver::external::*;
+use baba_solver::solver::greedy::GreedyBestFirst;
+use baba_solver::solver::heuristic::*;
+use baba_solver::solver::ida_star::*;
+use baba_solver::solver::limits::*;
+use baba_solver::solver::macro_moves::MacroBfs;
+use baba_solver::solver::mcts::MonteCarlo;
+use baba_solver::solver::optimal::AllShortest;
+use baba_solver::solver::parallel::ParallelBfs;
+use baba_solver::solver::portfolio::*;
+use baba_solver::solver::reachability::RuleReachability;
+use baba_solver::solver::rules_are_fixed;
+use baba_solver::solver::shorten::Shortener;
+use baba_solver::solver::stats::*;
 
-use interpreter::*;
-use levels_list::*;
+const USAGE: &str = "usage: baba_solver COMMAND [LEVEL] [OPTIONS]
+
+commands:
+    play [LEVEL]    plays the given level (1 by default) in the terminal
+    show [LEVEL]    prints the given level and its legend
+    rules [LEVEL]   lists the rules that are always active and the ones that can form
+    solve [LEVEL]   searches a solution of the given level
+    solutions [LEVEL]
+                    lists the shortest solutions of the given level
+    certify [LEVEL] explores every reachable state to certify the level has no solution
+    check [LEVEL]   checks the unsolvability certificate of the level
+    verify FILE     replays the solution file on its level
+    verify [LEVEL] --moves MOVES
+                    replays moves such as R12D3 (U, D, L, R, W and repeat counts)
+
+options:
+    --unicode               draws the objects with Unicode symbols
+    --colour                draws with ANSI colours
+    --solver NAME           solver to use: ida (default), bfs, bidirectional, parallel,
+                            external, macro, beam, greedy, mcts, portfolio
+    --heuristic NAME        heuristic to use: win-distance, blind (default with
+                            ida, which only finds the shortest solution with blind
+                            or on a level whose rules can't change)
+    --beam-width N          states kept in each layer by the beam solver (default 100)
+    --max-beam-width N      the beam is widened up to N states when it misses every
+                            solution (default: no widening)
+    --improve               the beam and greedy solvers keep looking for shorter
+                            solutions until a limit is hit
+    --portfolio NAMES       solvers raced by the portfolio solver, separated by commas
+                            (picked from the features of the level by default)
+    --seed N                seed of the random moves of the mcts solver (default 0)
+    --max-depth N           gives up after N moves (default 100)
+    --max-nodes N           gives up after expanding N states
+    --max-time N            gives up after N seconds
+    --max-memory N          gives up when the stored states take about N megabytes
+    --threads N             threads of the parallel solver (all the cores by default)
+    --dir PATH              working directory of the external solver, a run
+                            is resumed if the directory holds one
+    --batch-size N          states kept in memory by the external solver
+    --checkpoint PATH       saves the search in the file at intervals and on Ctrl-C,
+                            with every solver but external, which resumes from its --dir
+    --checkpoint-interval N seconds between two checkpoints (default 600)
+    --resume                continues the search saved in the checkpoint file
+    --count                 only counts the shortest solutions
+    --group-rules           groups the shortest solutions by the rules they go through
+    --max-solutions N       shortest solutions listed (default 20)
+    --certificate PATH      file the unsolvability certificate is written to or read from
+    --save PATH             writes the solution found to a solution file
+    --shorten               tries to shorten the solution found
+    --progress              reports the progress of the search every second
+    --stats                 prints the statistics of the search
+    --normalize             the macro solver explores a state once whatever square
+                            YOU walked to";
+
+/// Options followed by a value
+const VALUED_OPTIONS: [&str; 19] = [
+    "--solver",
+    "--heuristic",
+    "--portfolio",
+    "--beam-width",
+    "--max-beam-width",
+    "--seed",
+    "--max-solutions",
+    "--max-depth",
+    "--max-nodes",
+    "--max-time",
+    "--max-memory",
+    "--threads",
+    "--dir",
+    "--batch-size",
+    "--certificate",
+    "--save",
+    "--moves",
+    "--checkpoint",
+    "--checkpoint-interval",
+];
+
+/// Command line arguments, split between positional ones and options
+struct Args {
+    positional: Vec<String>,
+    options: HashMap<String, Option<String>>,
+}
+
+impl Args {
+    fn parse() -> Result<Self, String> {
+        let mut args = Self {
+            positional: vec![],
+            options: HashMap::new(),
+        };
+        let mut raw = env::args().skip(1);
+        while let Some(arg) = raw.next() {
+            if !arg.starts_with("--") {
+                args.positional.push(arg);
+            } else if VALUED_OPTIONS.contains(&arg.as_str()) {
+                let value = raw.next().ok_or(format!("missing value for {}", arg))?;
+                args.options.insert(arg, Some(value));
+            } else {
+                args.options.insert(arg, None);
+            }
+        }
+        Ok(args)
+    }
+
+    fn flag(&self, name: &str) -> bool {
+        self.options.contains_key(name)
+    }
+
+    fn value(&self, name: &str) -> Option<&str> {
+        self.options.get(name).and_then(|v| v.as_deref())
+    }
+
+    /// Parses the value of a numeric option
+    fn number(&self, name: &str, default: usize) -> Result<usize, String> {
+        self.value(name).map_or(Ok(default), |v| {
+            v.parse()
+                .map_err(|_| format!("invalid number for {}: {}", name, v))
+        })
+    }
+}
 
 fn main() {
-    println!("Hello, world!");
-    let level = &mut LEVELS_LIST[0].clone();
-    let result = level.apply_move_sequence(vec![
-        LEFT, LEFT, UP, UP, UP, RIGHT, RIGHT, RIGHT, RIGHT, RIGHT, RIGHT, RIGHT, RIGHT, RIGHT,
-        RIGHT, RIGHT, RIGHT, DOWN, DOWN, DOWN, LEFT, LEFT,
-    ]);
-
-    dbg!(result);
-    //dbg!(level);
-
-    let level = &mut LEVELS_LIST[0].clone();
-    let result = level.apply_move_sequence(vec![
-        RIGHT, RIGHT, RIGHT, RIGHT, RIGHT, DOWN, RIGHT, RIGHT, RIGHT, UP,
-    ]);
-
-    dbg!(result);
-    dbg!(level);
+    if let Err(message) = Args::parse().and_then(|args| run(&args)) {
+        eprintln!("{}", message);
+        process::exit(1);
+    }
+}
+
+fn run(args: &Args) -> Result<(), String> {
+    let renderer = Renderer {
+        unicode: args.flag("--unicode"),
+        colour: args.flag("--colour"),
+    };
+
+    match args.positional.first().map(String::as_str) {
+        Some("play") => {
+            let level = level_arg(args.positional.get(1))?;
+            play(level, renderer).map_err(|e| format!("terminal error: {}", e))
+        }
+        Some("show") => level_arg(args.positional.get(1)).map(|level| show(&level, renderer)),
+        Some("rules") => level_arg(args.positional.get(1)).map(|level| rules(&level)),
+        Some("solve") => solve(&level_arg(args.positional.get(1))?, args),
+        Some("solutions") => solutions(&level_arg(args.positional.get(1))?, args),
+        Some("certify") => certify(&level_arg(args.positional.get(1))?, args),
+        Some("check") => check(&level_arg(args.positional.get(1))?, args),
+        Some("verify") => match args.value("--moves") {
+            Some(moves) => verify_moves(&level_arg(args.positional.get(1))?, moves),
+            None => verify(args.positional.get(1).ok_or(USAGE)?),
+        },
+        _ => Err(USAGE.to_string()),
+    }
+}
+
+/// Returns the level number given in the arguments, 1 by default
+fn level_number(arg: Option<&String>) -> Result<usize, String> {
+    match arg {
+        Some(arg) => arg
+            .parse::<usize>()
+            .map_err(|_| format!("invalid level number: {}", arg)),
+        None => Ok(1),
+    }
+}
+
+/// Returns the level with the given number, starting from 1
+fn level_arg(arg: Option<&String>) -> Result<Level, String> {
+    level_by_number(level_number(arg)?)
+}
+
+fn level_by_number(number: usize) -> Result<Level, String> {
+    number
+        .checked_sub(1)
+        .and_then(|index| LEVELS_LIST.get(index))
+        .cloned()
+        .ok_or_else(|| {
+            format!(
+                "no level {}, there are {} levels",
+                number,
+                LEVELS_LIST.len()
+            )
+        })
+}
+
+/// Prints a level with its legend
+fn show(level: &Level, renderer: Renderer) {
+    for line in renderer.render_grid(&level.grid) {
+        println!("{}", line);
+    }
+    println!();
+    for line in renderer.legend(&level.grid) {
+        println!("{}", line);
+    }
+}
+
+/// Prints the rules that are active in every state, then the other ones that can form
+fn rules(level: &Level) {
+    let analysis = RuleReachability::analyse(level);
+    println!("permanent rules:");
+    for (entity, text) in analysis.permanent.active_rules() {
+        println!("    {:?} IS {}", entity, text);
+    }
+    println!("rules that can form:");
+    for (entity, text) in analysis.formable.active_rules() {
+        if !analysis.permanent[entity][usize::from(text)] {
+            println!("    {:?} IS {}", entity, text);
+        }
+    }
+}
+
+/// Prints the shortest solutions of a level, their number or their groups
+fn solutions(level: &Level, args: &Args) -> Result<(), String> {
+    let mut solver = AllShortest::new(0).with_limits(limits_arg(args)?);
+    if let Some(progress) = progress_arg(args) {
+        solver = solver.with_progress(progress);
+    }
+    let report = solver.solve(level);
+    if args.flag("--progress") {
+        eprintln!();
+    }
+    let solutions = match (report.solutions, report.outcome) {
+        (Some(solutions), _) => solutions,
+        (None, SolveOutcome::LimitReached { limit, .. }) => {
+            println!("no solution found, the search stopped at the {}", limit);
+            return Ok(());
+        }
+        (None, _) => {
+            println!("the level has no solution");
+            return Ok(());
+        }
+    };
+    println!(
+        "{} shortest solutions in {} moves",
+        solutions.count(),
+        solutions.length()
+    );
+    if args.flag("--group-rules") {
+        for group in solutions.group_by_rules(level) {
+            println!();
+            println!(
+                "{} solutions, such as {}",
+                group.count,
+                format_moves(&group.example)
+            );
+            for rules in group.rules.windows(2) {
+                let (before, after) = (rules[0].active_rules(), rules[1].active_rules());
+                let removed = before.iter().filter(|rule| !after.contains(rule));
+                let added = after.iter().filter(|rule| !before.contains(rule));
+                let changes: Vec<String> = removed
+                    .map(|(entity, text)| format!("-{:?} IS {}", entity, text))
+                    .chain(added.map(|(entity, text)| format!("+{:?} IS {}", entity, text)))
+                    .collect();
+                println!("    {}", changes.join(", "));
+            }
+        }
+    } else if !args.flag("--count") {
+        for moves in solutions.list(args.number("--max-solutions", 20)?) {
+            println!("{}", format_moves(&moves));
+        }
+    }
+    if args.flag("--stats") {
+        println!();
+        println!("{}", report.stats);
+    }
+    Ok(())
+}
+
+/// Explores a level to certify it has no solution, prints the certificate
+/// and writes it to the given file
+fn certify(level: &Level, args: &Args) -> Result<(), String> {
+    let mut certifier = Certifier::new().with_limits(limits_arg(args)?);
+    if let Some(progress) = progress_arg(args) {
+        certifier = certifier.with_progress(progress);
+    }
+    let report = certifier.certify(level);
+    if args.flag("--progress") {
+        eprintln!();
+    }
+    match (report.certificate, report.outcome) {
+        (Some(certificate), _) => {
+            print!("{}", certificate);
+            if let Some(path) = args.value("--certificate") {
+                std::fs::write(path, certificate.to_string())
+                    .map_err(|e| format!("cannot write {}: {}", path, e))?;
+            }
+        }
+        (None, SolveOutcome::Solved(moves)) => {
+            println!(
+                "the level has a solution in {} moves: {}",
+                moves.len(),
+                format_moves(&moves)
+            )
+        }
+        (None, SolveOutcome::LimitReached { limit, .. }) => {
+            println!("no certificate, the search stopped at the {}", limit)
+        }
+        (None, SolveOutcome::Unsolvable) => unreachable!("An unsolvable level is certified"),
+    }
+    if args.flag("--stats") {
+        println!();
+        println!("{}", report.stats);
+    }
+    Ok(())
+}
+
+/// Checks the unsolvability certificate in the given file
+fn check(level: &Level, args: &Args) -> Result<(), String> {
+    let path = args
+        .value("--certificate")
+        .ok_or("the check needs a --certificate")?;
+    let text = std::fs::read_to_string(path).map_err(|e| format!("cannot read {}: {}", path, e))?;
+    let certificate =
+        Certificate::parse(&text).map_err(|e| format!("invalid certificate: {}", e))?;
+    match certificate.check(level, &limits_arg(args)?) {
+        Ok(()) => println!("the certificate holds, the level has no solution"),
+        Err(error) => return Err(format!("the certificate doesn't hold: {}", error)),
+    }
+    Ok(())
+}
+
+/// Replays the solution file on its level
+fn verify(path: &str) -> Result<(), String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("cannot read {}: {}", path, e))?;
+    let file = SolutionFile::parse(&text).map_err(|e| format!("invalid solution file: {}", e))?;
+    let level = level_by_number(file.level)?;
+    match file.verify(&level) {
+        Ok(at) => println!("the solution wins level {} at move {}", file.level, at),
+        Err(error) => return Err(format!("the solution is wrong: {}", error)),
+    }
+    Ok(())
+}
+
+/// Replays moves written in the compact notation on the level
+fn verify_moves(level: &Level, moves: &str) -> Result<(), String> {
+    let moves = parse_moves(moves).map_err(|e| format!("invalid moves: {}", e))?;
+    match replay(level, &moves) {
+        Ok(at) => println!("the moves win the level at move {}", at),
+        Err(error) => return Err(format!("the moves are wrong: {}", error)),
+    }
+    Ok(())
+}
+
+/// Returns the checkpoint configuration given in the options, Ctrl-C is then
+/// caught to save a last checkpoint
+fn checkpoint_arg(args: &Args) -> Result<Option<CheckpointConfig>, String> {
+    let path = match args.value("--checkpoint") {
+        Some(path) => path,
+        None if args.flag("--resume") => return Err("--resume needs a --checkpoint".to_string()),
+        None => return Ok(None),
+    };
+    // The external solver resumes from its directory
+    if args.value("--solver") == Some("external") {
+        return Err("the external solver resumes from its --dir, not a checkpoint".to_string());
+    }
+    let interval = args.number("--checkpoint-interval", 600)?;
+    catch_interruptions();
+    Ok(Some(CheckpointConfig {
+        path: path.into(),
+        interval: Duration::from_secs(interval as u64),
+        resume: args.flag("--resume"),
+    }))
+}
+
+/// Returns the search limits given in the options
+fn limits_arg(args: &Args) -> Result<Limits, String> {
+    let optional = |name| -> Result<Option<usize>, String> {
+        match args.value(name) {
+            Some(_) => args.number(name, 0).map(Some),
+            None => Ok(None),
+        }
+    };
+    Ok(Limits {
+        max_nodes: optional("--max-nodes")?.map(|n| n as u64),
+        max_time: optional("--max-time")?.map(|secs| Duration::from_secs(secs as u64)),
+        max_memory: optional("--max-memory")?.map(|mb| mb << 20),
+        ..Limits::depth(args.number("--max-depth", 100)?)
+    })
+}
+
+/// Returns the live progress report asked in the options, printed on the error output
+fn progress_arg(args: &Args) -> Option<Progress> {
+    if !args.flag("--progress") {
+        return None;
+    }
+    Some(Progress {
+        interval: Duration::from_secs(1),
+        callback: Arc::new(|stats: &SearchStats| {
+            eprint!(
+                "\rexpanded {}, generated {}, duplicates {}, depth {}, {:.0}s ",
+                stats.expanded,
+                stats.generated,
+                stats.duplicates,
+                stats.frontier_by_depth.len().saturating_sub(1),
+                stats.elapsed.as_secs_f64()
+            );
+        }),
+    })
+}
+
+/// Runs a search, with checkpoints if the options configure them
+fn checkpointed<T>(
+    checkpoint: Option<CheckpointConfig>,
+    solve: impl FnOnce() -> T,
+    solve_with_checkpoints: impl FnOnce(CheckpointConfig) -> io::Result<T>,
+) -> Result<T, String> {
+    match checkpoint {
+        Some(config) => {
+            solve_with_checkpoints(config).map_err(|e| format!("search stopped: {}", e))
+        }
+        None => Ok(solve()),
+    }
+}
+
+/// Runs the iterative-deepening A* solver and prints its iterations
+fn solve_ida<H: Heuristic>(
+    heuristic: H,
+    level: &Level,
+    limits: Limits,
+    progress: Option<Progress>,
+    checkpoint: Option<CheckpointConfig>,
+) -> Result<SolveReport, String> {
+    let mut solver = IdaStar::new(heuristic, DEFAULT_TABLE_SIZE, 0).with_limits(limits);
+    if let Some(progress) = progress {
+        solver = solver.with_progress(progress);
+    }
+    let result = checkpointed(
+        checkpoint,
+        || solver.solve(level),
+        |config| solver.solve_with_checkpoints(level, config),
+    )?;
+    for bound in &result.bounds {
+        println!("iteration with f-bound {}", bound);
+    }
+    Ok(SolveReport {
+        outcome: result.outcome,
+        stats: result.stats,
+    })
+}
+
+/// Runs the beam, the greedy best-first or the Monte Carlo solver
+fn solve_guided<H: Heuristic>(
+    heuristic: H,
+    level: &Level,
+    limits: Limits,
+    progress: Option<Progress>,
+    checkpoint: Option<CheckpointConfig>,
+    args: &Args,
+) -> Result<SolveReport, String> {
+    let max_depth = limits.max_depth;
+    if args.value("--solver") == Some("mcts") {
+        let seed = args.number("--seed", 0)?;
+        let mut solver = MonteCarlo::new(heuristic, seed as u64, max_depth).with_limits(limits);
+        if let Some(progress) = progress {
+            solver = solver.with_progress(progress);
+        }
+        checkpointed(
+            checkpoint,
+            || solver.solve(level),
+            |config| solver.solve_with_checkpoints(level, config),
+        )
+    } else if args.value("--solver") == Some("beam") {
+        let width = args.number("--beam-width", DEFAULT_BEAM_WIDTH)?;
+        let max_width = args.number("--max-beam-width", width)?;
+        let mut solver = BeamSearch::new(heuristic, width, max_depth)
+            .with_max_width(max_width)
+            .with_limits(limits);
+        if let Some(progress) = progress {
+            solver = solver.with_progress(progress);
+        }
+        if args.flag("--improve") {
+            solver = solver.improving();
+        }
+        checkpointed(
+            checkpoint,
+            || solver.solve(level),
+            |config| solver.solve_with_checkpoints(level, config),
+        )
+    } else {
+        let mut solver = GreedyBestFirst::new(heuristic, max_depth).with_limits(limits);
+        if let Some(progress) = progress {
+            solver = solver.with_progress(progress);
+        }
+        if args.flag("--improve") {
+            solver = solver.improving();
+        }
+        checkpointed(
+            checkpoint,
+            || solver.solve(level),
+            |config| solver.solve_with_checkpoints(level, config),
+        )
+    }
+}
+
+/// Searches a solution with the solver given in the options and prints it
+fn solve(level: &Level, args: &Args) -> Result<(), String> {
+    let limits = limits_arg(args)?;
+    let max_depth = limits.max_depth;
+    let progress = progress_arg(args);
+    let checkpoint = checkpoint_arg(args)?;
+    let report = match args.value("--solver").unwrap_or("ida") {
+        "ida" => match args.value("--heuristic").unwrap_or("blind") {
+            "win-distance" => {
+                if !rules_are_fixed(level) {
+                    println!("the rules can change, the solution may not be the shortest");
+                }
+                solve_ida(WinDistance, level, limits, progress, checkpoint)?
+            }
+            "blind" => solve_ida(Blind, level, limits, progress, checkpoint)?,
+            name => return Err(format!("unknown heuristic: {}", name)),
+        },
+        "beam" | "greedy" | "mcts" => match args.value("--heuristic").unwrap_or("win-distance") {
+            "win-distance" => solve_guided(WinDistance, level, limits, progress, checkpoint, args)?,
+            "blind" => solve_guided(Blind, level, limits, progress, checkpoint, args)?,
+            name => return Err(format!("unknown heuristic: {}", name)),
+        },
+        "bfs" => {
+            let mut solver = Bfs::new(max_depth).with_limits(limits);
+            if let Some(progress) = progress {
+                solver = solver.with_progress(progress);
+            }
+            checkpointed(
+                checkpoint,
+                || solver.solve(level),
+                |config| solver.solve_with_checkpoints(level, config),
+            )?
+        }
+        "external" => {
+            let dir = args
+                .value("--dir")
+                .ok_or("the external solver needs a --dir")?;
+            let batch_size = args.number("--batch-size", DEFAULT_BATCH_SIZE)?;
+            let mut solver =
+                ExternalBfs::new(dir.into(), max_depth, batch_size).with_limits(limits);
+            if let Some(progress) = progress {
+                solver = solver.with_progress(progress);
+            }
+            // The directory is the checkpoint of the external solver
+            catch_interruptions();
+            solver
+                .solve(level)
+                .map_err(|e| format!("external search failed: {}", e))?
+        }
+        "parallel" => {
+            let threads = args.number("--threads", ParallelBfs::available_threads())?;
+            let mut solver = ParallelBfs::new(max_depth, threads).with_limits(limits);
+            if let Some(progress) = progress {
+                solver = solver.with_progress(progress);
+            }
+            checkpointed(
+                checkpoint,
+                || solver.solve(level),
+                |config| solver.solve_with_checkpoints(level, config),
+            )?
+        }
+        "macro" => {
+            let mut solver = MacroBfs::new(max_depth).with_limits(limits);
+            if let Some(progress) = progress {
+                solver = solver.with_progress(progress);
+            }
+            if args.flag("--normalize") {
+                solver = solver.with_normalized_keys();
+            }
+            checkpointed(
+                checkpoint,
+                || solver.solve(level),
+                |config| solver.solve_with_checkpoints(level, config),
+            )?
+        }
+        "portfolio" => {
+            let mut solver = match args.value("--portfolio") {
+                Some("") => return Err("--portfolio needs at least one solver".to_string()),
+                Some(names) => {
+                    let strategies = names
+                        .split(',')
+                        .map(|name| {
+                            Strategy::from_name(name)
+                                .ok_or_else(|| format!("unknown solver: {}", name))
+                        })
+                        .collect::<Result<_, _>>()?;
+                    PortfolioSolver::new(strategies, max_depth)
+                }
+                None => PortfolioSolver::for_level(level, max_depth),
+            };
+            solver = solver.with_limits(limits);
+            let report = checkpointed(
+                checkpoint,
+                || solver.solve(level),
+                |config| solver.solve_with_checkpoints(level, config),
+            )?;
+            match report.winner {
+                Some(winner) => println!("won by {}", winner),
+                None => println!("no strategy won"),
+            }
+            SolveReport {
+                outcome: report.outcome,
+                stats: report.stats,
+            }
+        }
+        "bidirectional" => {
+            if !Bidirectional::applies(level) {
+                println!("no backward search on this level, falling back to a forward search");
+            }
+            let mut solver = Bidirectional::new(max_depth).with_limits(limits);
+            if let Some(progress) = progress {
+                solver = solver.with_progress(progress);
+            }
+            checkpointed(
+                checkpoint,
+                || solver.solve(level),
+                |config| solver.solve_with_checkpoints(level, config),
+            )?
+        }
+        name => return Err(format!("unknown solver: {}", name)),
+    };
+    if args.flag("--progress") {
+        eprintln!();
+    }
+
+    match report.outcome {
+        SolveOutcome::Solved(mut moves) => {
+            println!(
+                "solution in {} moves: {}",
+                moves.len(),
+                format_moves(&moves)
+            );
+            if args.flag("--shorten") {
+                let shortened = Shortener::new()
+                    .shorten(level, &moves)
+                    .ok_or("the solution doesn't win the level")?;
+                if shortened.len() < moves.len() {
+                    println!(
+                        "shortened to {} moves: {}",
+                        shortened.len(),
+                        format_moves(&shortened)
+                    );
+                    moves = shortened;
+                }
+            }
+            if let Some(path) = args.value("--save") {
+                let number = level_number(args.positional.get(1))?;
+                let solver = args.value("--solver").unwrap_or("ida");
+                let file = SolutionFile::new(number, level, moves, solver, &report.stats);
+                std::fs::write(path, file.to_string())
+                    .map_err(|e| format!("cannot write {}: {}", path, e))?;
+            }
+        }
+        SolveOutcome::Unsolvable => println!("the level has no solution"),
+        SolveOutcome::LimitReached { limit, best } => {
+            println!("no solution found, the search stopped at the {}", limit);
+            if let Some(best) = best {
+                println!(
+                    "closest state, {} squares from a win: {}",
+                    best.win_distance,
+                    format_moves(&best.moves)
+                );
+            }
+        }
+    }
+    if args.flag("--stats") {
+        println!();
+        println!("{}", report.stats);
+    }
+    Ok(())
 }
@@ -0,0 +1,92 @@
+//! A compact notation for move sequences: one letter per move, U, D, L, R and W
+//! for waiting, each one optionally followed by a repeat count, so that
+//! `R12D3` is twelve moves right then three down
+
+use std::io;
+
+use crate::encoding::invalid;
+use crate::interpreter::*;
+
+/// Longest run accepted by the parser, to reject counts that can't be meant
+pub const MAX_RUN: usize = 1_000_000;
+
+pub fn move_letter(m: Move) -> char {
+    match m {
+        Move::Up => 'U',
+        Move::Down => 'D',
+        Move::Left => 'L',
+        Move::Right => 'R',
+        Move::Wait => 'W',
+    }
+}
+
+pub fn letter_move(letter: char) -> Option<Move> {
+    match letter {
+        'U' => Some(UP),
+        'D' => Some(DOWN),
+        'L' => Some(LEFT),
+        'R' => Some(RIGHT),
+        'W' => Some(WAIT),
+        _ => None,
+    }
+}
+
+/// Writes the moves with the count of each run longer than one move
+pub fn format_moves(moves: &[Move]) -> String {
+    let mut text = String::new();
+    let mut runs = moves.iter().peekable();
+    while let Some(&m) = runs.next() {
+        let mut run = 1;
+        while runs.next_if_eq(&&m).is_some() {
+            run += 1;
+        }
+        text.push(move_letter(m));
+        if run > 1 {
+            text.push_str(&run.to_string());
+        }
+    }
+    text
+}
+
+/// Reads moves in the notation, rejecting anything else than the letters and
+/// counts from 1 to `MAX_RUN` with no leading zero
+pub fn parse_moves(text: &str) -> io::Result<Vec<Move>> {
+    let mut moves = vec![];
+    let mut chars = text.char_indices().peekable();
+    while let Some((i, letter)) = chars.next() {
+        let m = letter_move(letter)
+            .ok_or_else(|| invalid(&format!("invalid move {:?} at {}", letter, i)))?;
+        let mut digits = String::new();
+        while let Some((_, digit)) = chars.next_if(|(_, c)| c.is_ascii_digit()) {
+            digits.push(digit);
+        }
+        let run = if digits.is_empty() {
+            1
+        } else {
+            digits
+                .parse::<usize>()
+                .ok()
+                .filter(|&run| !digits.starts_with('0') && run <= MAX_RUN)
+                .ok_or_else(|| invalid(&format!("invalid count {} at {}", digits, i + 1)))?
+        };
+        moves.extend(std::iter::repeat(m).take(run));
+    }
+    Ok(moves)
+}
+
+#[test]
+fn run_length_round_trip() {
+    let moves = parse_moves("R12D3ULW2").expect("The notation is valid");
+    assert_eq!(moves.len(), 19);
+    assert_eq!(&moves[10..15], &[RIGHT, RIGHT, DOWN, DOWN, DOWN]);
+    assert_eq!(format_moves(&moves), "R12D3ULW2");
+    assert_eq!(
+        format_moves(&parse_moves("RRRU").expect("Plain letters")),
+        "R3U"
+    );
+    assert_eq!(parse_moves("").ok(), Some(vec![]));
+
+    for wrong in &["R0", "R03", "r2", "R 2", "2R", "X", "R99999999999999999999"] {
+        assert!(parse_moves(wrong).is_err(), "{} was accepted", wrong);
+    }
+}
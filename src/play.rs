@@ -0,0 +1,184 @@
+//! An interactive mode to play a level in a terminal
+//! The terminal is switched to raw mode with `stty` while playing, so this
+//! works in a plain Linux terminal. Ctrl-C is read as a key and quits, so the
+//! terminal is always restored
+
+use std::io::{self, Read, Write};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+use crate::interpreter::*;
+use crate::level::Level;
+use crate::render::Renderer;
+use crate::session::GameSession;
+
+const HELP: &str = "arrows/wasd: move  space: wait  z: undo  y: redo  r: restart  q/esc: quit";
+
+/// Time a read waits for a key press, set with `stty time`
+const READ_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Number of reads in a row returning nothing without waiting after which
+/// stdin is taken as closed
+const EOF_READS: usize = 3;
+
+/// An action asked by the player
+enum Key {
+    Move(Move),
+    Undo,
+    Redo,
+    Restart,
+    Quit,
+    Unknown,
+}
+
+/// Puts the terminal in raw mode and restores it when dropped
+struct RawTerminal {
+    saved: String,
+}
+
+impl RawTerminal {
+    fn enable() -> io::Result<Self> {
+        let saved = stty(&["-g"])?;
+        // Reads return after a tenth of a second without input, to tell a lone
+        // Escape press from an escape sequence
+        stty(&["-icanon", "-echo", "-isig", "min", "0", "time", "1"])?;
+        Ok(Self {
+            saved: saved.trim().to_string(),
+        })
+    }
+}
+
+impl Drop for RawTerminal {
+    fn drop(&mut self) {
+        // Nothing more can be done if the terminal cannot be restored
+        let _ = stty(&[&self.saved]);
+    }
+}
+
+/// Runs stty on the current terminal and returns its output
+fn stty(args: &[&str]) -> io::Result<String> {
+    let output = Command::new("stty")
+        .args(args)
+        .stdin(Stdio::inherit())
+        .output()?;
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::Other,
+            String::from_utf8_lossy(&output.stderr).into_owned(),
+        ))
+    }
+}
+
+/// Plays the given level until the player quits
+pub fn play(level: Level, renderer: Renderer) -> io::Result<()> {
+    let _terminal = RawTerminal::enable()?;
+    let mut session = GameSession::new(level);
+    let mut stdin = io::stdin();
+
+    loop {
+        draw(&session, renderer)?;
+        match read_key(&mut stdin)? {
+            Key::Move(m) => {
+                session.play(m);
+            }
+            Key::Undo => {
+                session.undo();
+            }
+            Key::Redo => {
+                session.redo();
+            }
+            Key::Restart => session.restart(),
+            Key::Quit => return Ok(()),
+            Key::Unknown => (),
+        }
+    }
+}
+
+/// Reads a key press, arrows are sent as escape sequences.
+/// A closed stdin is read as a request to quit
+fn read_key(stdin: &mut io::Stdin) -> io::Result<Key> {
+    let mut eof_reads = 0;
+    let byte = loop {
+        let start = Instant::now();
+        match read_byte(stdin)? {
+            Some(byte) => break byte,
+            // Nothing was typed before the timeout
+            None if start.elapsed() >= READ_TIMEOUT / 2 => eof_reads = 0,
+            None => {
+                eof_reads += 1;
+                if eof_reads == EOF_READS {
+                    return Ok(Key::Quit);
+                }
+            }
+        }
+    };
+    Ok(match byte {
+        b'w' | b'k' => Key::Move(UP),
+        b'a' | b'h' => Key::Move(LEFT),
+        b's' | b'j' => Key::Move(DOWN),
+        b'd' | b'l' => Key::Move(RIGHT),
+        b' ' | b'.' => Key::Move(WAIT),
+        b'z' | b'u' => Key::Undo,
+        b'y' => Key::Redo,
+        b'r' => Key::Restart,
+        // Ctrl-C
+        b'q' | 0x03 => Key::Quit,
+        0x1b => match read_byte(stdin)? {
+            // A lone Escape press
+            None => Key::Quit,
+            Some(b'[') => match read_byte(stdin)? {
+                Some(b'A') => Key::Move(UP),
+                Some(b'B') => Key::Move(DOWN),
+                Some(b'C') => Key::Move(RIGHT),
+                Some(b'D') => Key::Move(LEFT),
+                _ => Key::Unknown,
+            },
+            Some(_) => Key::Unknown,
+        },
+        _ => Key::Unknown,
+    })
+}
+
+/// Reads a byte, None if nothing was typed for a tenth of a second or at the end of stdin
+fn read_byte(stdin: &mut io::Stdin) -> io::Result<Option<u8>> {
+    let mut byte = [0; 1];
+    Ok(match stdin.read(&mut byte)? {
+        0 => None,
+        _ => Some(byte[0]),
+    })
+}
+
+/// Draws the board with the active rules and the legend on its right
+fn draw(session: &GameSession, renderer: Renderer) -> io::Result<()> {
+    let level = session.level();
+    let board = renderer.render_grid(&level.grid);
+    let blank = " ".repeat(level.grid.width());
+    let mut side: Vec<String> = level
+        .rules
+        .active_rules()
+        .iter()
+        .map(|(entity, text)| format!("{:?} IS {}", entity, text))
+        .collect();
+    side.push(String::new());
+    side.extend(renderer.legend(&level.grid));
+
+    let mut out = io::stdout();
+    // Clearing the screen and going back to the top left corner
+    write!(out, "\x1b[2J\x1b[H")?;
+    for i in 0..board.len().max(side.len()) {
+        let line = board.get(i).unwrap_or(&blank);
+        let side = side.get(i).map_or("", String::as_str);
+        writeln!(out, "{}   {}", line, side)?;
+    }
+    let status = match session.game_state() {
+        Some(EndState::Win) => "You win!",
+        Some(EndState::Defeat) => "Nothing is YOU, undo or restart",
+        None => "",
+    };
+    writeln!(out)?;
+    writeln!(out, "moves: {}  {}", session.moves().len(), status)?;
+    writeln!(out, "{}", HELP)?;
+    out.flush()
+}
@@ -0,0 +1,151 @@
+//! A module rendering a level as text, one glyph per square
+//! Objects are uppercase letters or symbols and text tiles are lowercase letters
+//! or punctuation. Text tiles are also shown in reverse video when colours are enabled
+
+use std::fmt;
+
+use crate::grid::Grid;
+use crate::square::*;
+
+/// Objects drawn on top of the others when they are stacked, text tiles are always on top
+const DRAW_PRIORITY: [Entity; 4] = [Entity::BABA, Entity::ROCK, Entity::FLAG, Entity::WALL];
+
+const RESET: &str = "\x1b[0m";
+
+/// Rendering options
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Renderer {
+    /// Uses Unicode symbols for the objects instead of ASCII letters
+    pub unicode: bool,
+    /// Uses ANSI escape codes for colours
+    pub colour: bool,
+}
+
+impl Renderer {
+    /// Returns the glyph representing a layer
+    pub fn glyph(&self, layer: LayeredSquare) -> char {
+        match layer {
+            LayeredSquare::Entity(e) => self.object_glyph(e),
+            LayeredSquare::Text(Text::Entity(Entity::BABA)) => 'b',
+            LayeredSquare::Text(Text::Entity(Entity::FLAG)) => 'f',
+            LayeredSquare::Text(Text::Entity(Entity::WALL)) => 'w',
+            LayeredSquare::Text(Text::Entity(Entity::ROCK)) => 'r',
+            LayeredSquare::Text(Text::Entity(Entity::EMPTY)) => 'e',
+            LayeredSquare::Text(Text::Entity(Entity::TEXT)) => 't',
+            LayeredSquare::Text(Text::Property(Property::YOU)) => 'y',
+            LayeredSquare::Text(Text::Property(Property::WIN)) => 'v',
+            LayeredSquare::Text(Text::Property(Property::STOP)) => 's',
+            LayeredSquare::Text(Text::Property(Property::PUSH)) => 'p',
+            LayeredSquare::Text(Text::Property(Property::DEFEAT)) => 'd',
+            LayeredSquare::Text(Text::Is) => '=',
+            LayeredSquare::Text(Text::And) => '&',
+        }
+    }
+
+    fn object_glyph(&self, entity: Entity) -> char {
+        match (entity, self.unicode) {
+            (Entity::BABA, false) => 'B',
+            (Entity::FLAG, false) => 'F',
+            (Entity::WALL, false) => '#',
+            (Entity::ROCK, false) => 'R',
+            (_, false) => '.',
+            (Entity::BABA, true) => '☺',
+            (Entity::FLAG, true) => '⚑',
+            (Entity::WALL, true) => '█',
+            (Entity::ROCK, true) => '●',
+            (_, true) => '·',
+        }
+    }
+
+    /// ANSI style of a layer
+    fn style(&self, layer: LayeredSquare) -> &'static str {
+        match layer {
+            LayeredSquare::Entity(Entity::BABA) => "\x1b[97m",
+            LayeredSquare::Entity(Entity::FLAG) => "\x1b[93m",
+            LayeredSquare::Entity(Entity::WALL) => "\x1b[90m",
+            LayeredSquare::Entity(Entity::ROCK) => "\x1b[33m",
+            LayeredSquare::Entity(_) => "\x1b[90m",
+            LayeredSquare::Text(Text::Entity(_)) => "\x1b[7;95m",
+            LayeredSquare::Text(Text::Property(_)) => "\x1b[7;96m",
+            LayeredSquare::Text(_) => "\x1b[7;97m",
+        }
+    }
+
+    /// Renders a square, only its top layer is shown
+    pub fn render_square(&self, square: Square) -> String {
+        let layer = top_layer(square).unwrap_or(LayeredSquare::Entity(Entity::EMPTY));
+        if self.colour {
+            format!("{}{}{}", self.style(layer), self.glyph(layer), RESET)
+        } else {
+            self.glyph(layer).to_string()
+        }
+    }
+
+    /// Renders the grid line by line
+    pub fn render_grid(&self, grid: &Grid) -> Vec<String> {
+        (0..grid.height())
+            .map(|y| {
+                (0..grid.width())
+                    .map(|x| self.render_square(grid[(x, y)]))
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Describes the glyphs of all the layers present in the grid
+    pub fn legend(&self, grid: &Grid) -> Vec<String> {
+        (0..LAYERED_SQUARES_NUMBER)
+            .map(LayeredSquare::from)
+            .filter(|&layer| !grid[layer].is_empty())
+            .map(|layer| {
+                let description = match layer {
+                    LayeredSquare::Entity(e) => format!("{:?}", e).to_lowercase(),
+                    LayeredSquare::Text(t) => format!("text {}", t),
+                };
+                let glyph = self.render_square(square_of(layer));
+                format!("{} {}", glyph, description)
+            })
+            .collect()
+    }
+}
+
+/// Returns the layer drawn for a square, text tiles first then objects by priority
+pub fn top_layer(square: Square) -> Option<LayeredSquare> {
+    square
+        .into_iter()
+        .filter(|layer| matches!(layer, LayeredSquare::Text(_)))
+        .last()
+        .or_else(|| {
+            DRAW_PRIORITY
+                .iter()
+                .map(|&e| LayeredSquare::from(e))
+                .find(|&layer| square.has_layer(layer))
+        })
+}
+
+/// A square containing only the given layer
+fn square_of(layer: LayeredSquare) -> Square {
+    let mut square = Square::default();
+    square.add_layer(layer);
+    square
+}
+
+// Plain rendering of the grid, without colours
+impl fmt::Display for Grid {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for line in Renderer::default().render_grid(self) {
+            writeln!(f, "{}", line)?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn text_is_drawn_over_objects() {
+    let mut level = crate::level::Level::new(3, 1);
+    level.add_square(Entity::WALL.into(), (0, 0));
+    level.add_square(Entity::BABA.into(), (0, 0));
+    level.add_square(Entity::ROCK.into(), (1, 0));
+    level.add_square(TROCK.into(), (1, 0));
+    assert_eq!(level.grid.to_string(), "Br.\n");
+}
@@ -1,13 +1,15 @@
-//! A rule manager, the rules are manually updated by the level interface
+//! A rule manager, the rules are parsed from the text written on the grid
 //! This a 2D boolean table indexed by entity and text
 
+use std::convert::TryFrom;
 use std::ops::{Index, IndexMut};
 
+use crate::grid::Grid;
 use crate::square::*;
 
 type TextLine = [bool; TEXTS_NUMBER];
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct RuleManager([TextLine; Entity::VARIANT_COUNT]);
 
 impl Default for RuleManager {
@@ -39,10 +41,77 @@ impl RuleManager {
         self[entity][usize::from(property)] = true;
     }
 
+    /// Parses all the rules written on the grid on top of the default ones.
+    /// A rule reads left to right or top to bottom as
+    /// `ENTITY [AND ENTITY]* IS ENTITY|PROPERTY [AND ENTITY|PROPERTY]*`
+    pub fn parse(grid: &Grid) -> Self {
+        let mut manager = Self::default();
+        let axes: [(Step, Step); 2] = [(Grid::left, Grid::right), (Grid::up, Grid::down)];
+
+        for &is_pos in grid[LayeredSquare::from(TIS)].iter() {
+            for (backward, forward) in axes.iter() {
+                let subjects = read_words(grid, is_pos, *backward, |t| Entity::try_from(t).is_ok());
+                let targets = read_words(grid, is_pos, *forward, |t| t != TIS && t != TAND);
+                for entity in subjects
+                    .into_iter()
+                    .filter_map(|t| Entity::try_from(t).ok())
+                {
+                    for &target in &targets {
+                        manager.add_rule(entity, target);
+                    }
+                }
+            }
+        }
+
+        manager
+    }
+
+    /// Returns all the active rules as (entity, property or entity) pairs,
+    /// the default ones included
+    pub fn active_rules(&self) -> Vec<(Entity, Text)> {
+        ENTITIES
+            .iter()
+            .flat_map(|&entity| {
+                (0..TEXTS_NUMBER)
+                    .filter(move |&t| self[entity][t])
+                    .map(move |t| (entity, Text::from(t)))
+            })
+            .collect()
+    }
+
     /// Returns if the given square has the given property
     pub fn square_has_property(&self, square: Square, property: Text) -> bool {
         square
             .into_iter()
-            .any(|layer| self[Entity::from(layer)][usize::from(Text::from(property))])
+            .any(|layer| self[Entity::from(layer)][usize::from(property)])
     }
 }
+
+/// A step from a square to its neighbour in a given direction
+type Step = fn(&Grid, usize) -> Option<usize>;
+
+/// Reads the accepted words chained by AND from the square next to the given one
+fn read_words(grid: &Grid, mut pos: usize, step: Step, accept: fn(Text) -> bool) -> Vec<Text> {
+    let mut words = vec![];
+    while let Some(word_pos) = step(grid, pos) {
+        let found: Vec<Text> = texts(grid[word_pos]).filter(|&t| accept(t)).collect();
+        if found.is_empty() {
+            break;
+        }
+        words.extend(found);
+        // Looking for an AND to continue the chain
+        match step(grid, word_pos) {
+            Some(and_pos) if grid[and_pos].has_layer(LayeredSquare::from(TAND)) => pos = and_pos,
+            _ => break,
+        }
+    }
+    words
+}
+
+/// Iterates over the text layers of a square
+fn texts(square: Square) -> impl Iterator<Item = Text> {
+    square.into_iter().filter_map(|layer| match layer {
+        LayeredSquare::Text(t) => Some(t),
+        LayeredSquare::Entity(_) => None,
+    })
+}
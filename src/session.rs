@@ -0,0 +1,140 @@
+//! A game session around a level, keeping the history of the played moves
+//! Each turn only stores the squares it changed, as recorded by the phases of
+//! the turn, so that neither playing, undoing nor redoing copies the whole level
+
+use crate::interpreter::*;
+use crate::level::*;
+use crate::square::*;
+
+/// A square changed by a turn, in 1D coordinates
+#[derive(Clone, Copy, Debug)]
+struct SquareChange {
+    pos: usize,
+    before: Square,
+    after: Square,
+}
+
+/// A played turn and the changes it made to the grid
+#[derive(Clone, Debug)]
+struct Turn {
+    m: Move,
+    changes: Vec<SquareChange>,
+    game_state: GameState,
+}
+
+#[derive(Clone, Debug)]
+pub struct GameSession {
+    initial: Level,
+    level: Level,
+    history: Vec<Turn>,
+    undone: Vec<Turn>,
+}
+
+impl GameSession {
+    pub fn new(level: Level) -> Self {
+        Self {
+            initial: level.clone(),
+            level,
+            history: vec![],
+            undone: vec![],
+        }
+    }
+
+    /// The current state of the level
+    pub fn level(&self) -> &Level {
+        &self.level
+    }
+
+    /// The game state after the last played move
+    pub fn game_state(&self) -> GameState {
+        self.history.last().and_then(|turn| turn.game_state)
+    }
+
+    /// The moves played since the start of the level
+    pub fn moves(&self) -> Vec<Move> {
+        self.history.iter().map(|turn| turn.m).collect()
+    }
+
+    /// Plays a move and records it, the undone moves cannot be redone anymore
+    pub fn play(&mut self, m: Move) -> GameState {
+        let mut recorded = vec![];
+        let game_state = self.level.apply_move_recording(m, &mut recorded);
+        // The first record of a square holds its content before the turn
+        recorded.sort_by_key(|&(pos, _)| pos);
+        recorded.dedup_by_key(|&mut (pos, _)| pos);
+        let grid = &self.level.grid;
+        let changes = recorded
+            .into_iter()
+            .filter(|&(pos, before)| before != grid[pos])
+            .map(|(pos, before)| SquareChange {
+                pos,
+                before,
+                after: grid[pos],
+            })
+            .collect();
+
+        self.history.push(Turn {
+            m,
+            changes,
+            game_state,
+        });
+        self.undone.clear();
+        game_state
+    }
+
+    /// Cancels the last move, returns it or None if nothing was played
+    pub fn undo(&mut self) -> Option<Move> {
+        let turn = self.history.pop()?;
+        for change in &turn.changes {
+            self.level.set_square(change.pos, change.before);
+        }
+        self.level.update_rules();
+        let m = turn.m;
+        self.undone.push(turn);
+        Some(m)
+    }
+
+    /// Plays again the last undone move, returns it or None if nothing was undone
+    pub fn redo(&mut self) -> Option<Move> {
+        let turn = self.undone.pop()?;
+        for change in &turn.changes {
+            self.level.set_square(change.pos, change.after);
+        }
+        self.level.update_rules();
+        let m = turn.m;
+        self.history.push(turn);
+        Some(m)
+    }
+
+    /// Goes back to the start of the level, forgetting the whole history
+    pub fn restart(&mut self) {
+        self.level = self.initial.clone();
+        self.history.clear();
+        self.undone.clear();
+    }
+}
+
+#[test]
+fn undo_and_redo_restore_the_grid() {
+    let start = crate::levels_list::LEVELS_LIST[0].clone();
+    let mut session = GameSession::new(start.clone());
+    session.play(RIGHT);
+    // Baba moved to an empty square
+    assert_eq!(session.history[0].changes.len(), 2);
+    session.play(UP);
+    let after = session.level().grid.squares().to_vec();
+
+    assert_eq!(session.undo(), Some(UP));
+    assert_eq!(session.undo(), Some(RIGHT));
+    assert!(session.undo().is_none());
+    assert_eq!(session.level().grid.squares(), start.grid.squares());
+
+    session.redo();
+    session.redo();
+    assert_eq!(session.level().grid.squares(), &after[..]);
+    assert_eq!(session.moves().len(), 2);
+
+    session.restart();
+    assert!(session.moves().is_empty());
+    assert_eq!(session.level().grid.squares(), start.grid.squares());
+}
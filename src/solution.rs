@@ -0,0 +1,231 @@
+//! Solution files, a winning move sequence with where it comes from
+//! A file is a list of `field value` lines, every field is required and they are
+//! written in a fixed order, any other line is rejected:
+//! ```text
+//! baba solution
+//! level 1
+//! level-hash 83c2bd8e2a3ae0f1
+//! moves R8
+//! solver bfs
+//! expanded 1234
+//! generated 4936
+//! elapsed-ms 25
+//! timestamp 1760862000
+//! engine baba_solver 0.1.0
+//! ```
+//! The moves are written in the notation of the `notation` module
+
+use std::fmt;
+use std::io;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::encoding::*;
+use crate::interpreter::*;
+use crate::level::Level;
+use crate::notation::*;
+use crate::solver::stats::SearchStats;
+
+/// First line of a solution file
+const HEADER: &str = "baba solution";
+/// Name and version of the engine writing the files
+pub const ENGINE: &str = concat!(env!("CARGO_PKG_NAME"), " ", env!("CARGO_PKG_VERSION"));
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SolutionFile {
+    /// Number of the level, starting from 1
+    pub level: usize,
+    pub level_hash: u64,
+    pub moves: Vec<Move>,
+    pub solver: String,
+    pub expanded: u64,
+    pub generated: u64,
+    pub elapsed: Duration,
+    /// Seconds since the Unix epoch when the solution was found
+    pub timestamp: u64,
+    pub engine: String,
+}
+
+/// Why a solution file doesn't win its level
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum VerifyError {
+    /// The file was written for another level
+    OtherLevel,
+    /// The move, numbered from 1, loses the game
+    Defeat {
+        at: usize,
+        m: Move,
+    },
+    /// The move wins before the last one
+    EarlyWin {
+        at: usize,
+        m: Move,
+    },
+    NoWin,
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VerifyError::OtherLevel => write!(f, "the solution is for another level"),
+            VerifyError::Defeat { at, m } => {
+                write!(f, "move {} ({}) loses the game", at, move_letter(*m))
+            }
+            VerifyError::EarlyWin { at, m } => write!(
+                f,
+                "move {} ({}) wins before the last move",
+                at,
+                move_letter(*m)
+            ),
+            VerifyError::NoWin => write!(f, "the moves don't win"),
+        }
+    }
+}
+
+impl SolutionFile {
+    /// Records a solution found now by the given solver
+    pub fn new(
+        number: usize,
+        level: &Level,
+        moves: Vec<Move>,
+        solver: &str,
+        stats: &SearchStats,
+    ) -> Self {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_secs());
+        Self {
+            level: number,
+            level_hash: level_hash(level),
+            moves,
+            solver: solver.to_string(),
+            expanded: stats.expanded,
+            generated: stats.generated,
+            elapsed: stats.elapsed,
+            timestamp,
+            engine: ENGINE.to_string(),
+        }
+    }
+
+    /// Replays the moves on the level, returns the number of the winning move,
+    /// which must be the last one
+    pub fn verify(&self, level: &Level) -> Result<usize, VerifyError> {
+        if level_hash(level) != self.level_hash {
+            return Err(VerifyError::OtherLevel);
+        }
+        replay(level, &self.moves)
+    }
+
+    /// Reads a solution file, rejecting any missing, unknown or misplaced line
+    pub fn parse(text: &str) -> io::Result<Self> {
+        let mut lines = text.lines().enumerate();
+        if lines.next().map(|(_, line)| line) != Some(HEADER) {
+            return Err(invalid("not a solution file"));
+        }
+        let mut field = |name: &str| {
+            let (number, line) = lines
+                .next()
+                .ok_or_else(|| invalid(&format!("missing {}", name)))?;
+            line.strip_prefix(name)
+                .and_then(|value| value.strip_prefix(' '))
+                .filter(|value| !value.is_empty())
+                .ok_or_else(|| invalid(&format!("line {}: expected {}", number + 1, name)))
+        };
+        let number = |name: &str, value: &str| {
+            value
+                .parse::<u64>()
+                .map_err(|_| invalid(&format!("invalid {}: {}", name, value)))
+        };
+
+        let level = number("level", field("level")?)? as usize;
+        let level_hash = field("level-hash")?;
+        let level_hash = u64::from_str_radix(level_hash, 16)
+            .ok()
+            .filter(|_| level_hash.len() == 16)
+            .ok_or_else(|| invalid(&format!("invalid level-hash: {}", level_hash)))?;
+        let moves = parse_moves(field("moves")?)?;
+        let solver = field("solver")?.to_string();
+        let expanded = number("expanded", field("expanded")?)?;
+        let generated = number("generated", field("generated")?)?;
+        let elapsed = Duration::from_millis(number("elapsed-ms", field("elapsed-ms")?)?);
+        let timestamp = number("timestamp", field("timestamp")?)?;
+        let engine = field("engine")?.to_string();
+        if let Some((number, _)) = lines.find(|(_, line)| !line.is_empty()) {
+            return Err(invalid(&format!("line {}: unexpected line", number + 1)));
+        }
+        Ok(Self {
+            level,
+            level_hash,
+            moves,
+            solver,
+            expanded,
+            generated,
+            elapsed,
+            timestamp,
+            engine,
+        })
+    }
+}
+
+/// Replays the moves on the level, returns the number of the winning move,
+/// which must be the last one
+pub fn replay(level: &Level, moves: &[Move]) -> Result<usize, VerifyError> {
+    let mut level = level.clone();
+    for (i, &m) in moves.iter().enumerate() {
+        match level.apply_move(m) {
+            Some(EndState::Win) if i + 1 == moves.len() => return Ok(i + 1),
+            Some(EndState::Win) => return Err(VerifyError::EarlyWin { at: i + 1, m }),
+            Some(EndState::Defeat) => return Err(VerifyError::Defeat { at: i + 1, m }),
+            None => (),
+        }
+    }
+    Err(VerifyError::NoWin)
+}
+
+impl fmt::Display for SolutionFile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{}", HEADER)?;
+        writeln!(f, "level {}", self.level)?;
+        writeln!(f, "level-hash {:016x}", self.level_hash)?;
+        writeln!(f, "moves {}", format_moves(&self.moves))?;
+        writeln!(f, "solver {}", self.solver)?;
+        writeln!(f, "expanded {}", self.expanded)?;
+        writeln!(f, "generated {}", self.generated)?;
+        writeln!(f, "elapsed-ms {}", self.elapsed.as_millis())?;
+        writeln!(f, "timestamp {}", self.timestamp)?;
+        writeln!(f, "engine {}", self.engine)
+    }
+}
+
+#[test]
+fn verifies_a_written_solution() {
+    let level = crate::levels_list::LEVELS_LIST[0].clone();
+    let stats = SearchStats::default();
+    let file = SolutionFile::new(1, &level, vec![RIGHT; 8], "bfs", &stats);
+    let parsed = SolutionFile::parse(&file.to_string()).expect("The file is valid");
+    assert_eq!(parsed, file);
+    assert_eq!(parsed.verify(&level), Ok(8));
+
+    let early = SolutionFile {
+        moves: vec![RIGHT; 9],
+        ..file.clone()
+    };
+    assert_eq!(
+        early.verify(&level),
+        Err(VerifyError::EarlyWin { at: 8, m: RIGHT })
+    );
+    assert_eq!(
+        replay(&level, &early.moves).map_err(|error| error.to_string()),
+        Err("move 8 (R) wins before the last move".to_string())
+    );
+    let short = SolutionFile {
+        moves: vec![RIGHT; 7],
+        ..file.clone()
+    };
+    assert_eq!(short.verify(&level), Err(VerifyError::NoWin));
+
+    // Strict parsing
+    let text = file.to_string();
+    assert!(SolutionFile::parse(&text.replace("moves R8", "moves R8X")).is_err());
+    assert!(SolutionFile::parse(&text.replace("solver bfs\n", "")).is_err());
+    assert!(SolutionFile::parse(&format!("{}comment\n", text)).is_err());
+}
@@ -0,0 +1,352 @@
+//! A beam search solver: a breadth first search only keeping the states with the
+//! best estimates in each layer, so its memory is bounded by the width of the beam
+//! A run that drops states can miss every solution, the search then stops at the beam
+//! width limit, or is run again with a beam twice as wide when a maximum width greater
+//! than the initial one is given. An improving search also runs again after a solution, only
+//! looking for shorter ones, until the budget runs out or a run drops nothing.
+//! The states reached at each depth are counted again in every run
+//!
+//! The checkpoints are saved between two runs, or in the middle of one when the
+//! process is interrupted or a limit is hit. A resumed search starts over the run
+//! it was saved in
+
+use std::collections::HashSet;
+use std::io;
+use std::time::{Duration, Instant};
+
+use crate::interpreter::*;
+use crate::level::Level;
+use crate::solver::checkpoint::*;
+use crate::solver::deadlock::Deadlocks;
+use crate::solver::heuristic::Heuristic;
+use crate::solver::limits::*;
+use crate::solver::reachability::RuleReachability;
+use crate::solver::stats::*;
+use crate::solver::*;
+
+/// Default number of states kept in each layer
+pub const DEFAULT_BEAM_WIDTH: usize = 100;
+
+pub struct BeamSearch<H: Heuristic> {
+    heuristic: H,
+    width: usize,
+    max_width: usize,
+    limits: Limits,
+    progress: Option<Progress>,
+    improving: bool,
+}
+
+/// How a run of the beam ended
+enum Run {
+    Solved(Vec<Move>),
+    /// No solution in the beam, and whether states were dropped or cut by the depth
+    Exhausted {
+        dropped: bool,
+        cut: bool,
+    },
+    Stopped(Stop),
+}
+
+/// The state of the whole search, shared by its runs
+struct Search<'a> {
+    level: &'a Level,
+    budget: Budget,
+    deadlocks: Deadlocks,
+    stats: SearchStats,
+    reporter: Reporter,
+    best: Best<Vec<Move>>,
+    start: Instant,
+    resumed_elapsed: Duration,
+    /// Stops the run when the process is interrupted
+    interruptible: bool,
+}
+
+impl<H: Heuristic> BeamSearch<H> {
+    /// Creates a solver keeping `width` states in each layer and ignoring
+    /// the solutions longer than `max_depth` moves
+    pub fn new(heuristic: H, width: usize, max_depth: usize) -> Self {
+        Self {
+            heuristic,
+            width: width.max(1),
+            max_width: width.max(1),
+            limits: Limits::depth(max_depth),
+            progress: None,
+            improving: false,
+        }
+    }
+
+    /// Replaces the limits of the search, including the maximum depth.
+    /// The memory limit counts the states of a single run
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Widens the beam up to `max_width` states when a run drops states and misses
+    /// every solution, instead of stopping at the beam width limit
+    pub fn with_max_width(mut self, max_width: usize) -> Self {
+        self.max_width = max_width.max(self.width);
+        self
+    }
+
+    /// Reports the statistics of the search while it runs
+    pub fn with_progress(mut self, progress: Progress) -> Self {
+        self.progress = Some(progress);
+        self
+    }
+
+    /// Keeps searching shorter solutions after the first one. The best solution is
+    /// returned when a limit is hit, it is the shortest one if the search ends by itself
+    pub fn improving(mut self) -> Self {
+        self.improving = true;
+        self
+    }
+
+    pub fn solve(&self, level: &Level) -> SolveReport {
+        self.search(level, None)
+            .expect("No I/O without checkpoints")
+    }
+
+    /// Searches a winning move sequence, saving checkpoints of the search.
+    /// A search stopped at a limit saves a checkpoint it can be resumed from
+    /// with greater limits
+    pub fn solve_with_checkpoints(
+        &self,
+        level: &Level,
+        config: CheckpointConfig,
+    ) -> io::Result<SolveReport> {
+        self.search(level, Some(Checkpointer::new(config, level, "beam")))
+    }
+
+    fn search(
+        &self,
+        level: &Level,
+        mut checkpointer: Option<Checkpointer>,
+    ) -> io::Result<SolveReport> {
+        let mut search = Search {
+            level,
+            budget: Budget::new(&self.limits, level),
+            deadlocks: Deadlocks::new(level),
+            stats: SearchStats::default(),
+            reporter: Reporter::new(&self.progress),
+            best: Best::default(),
+            start: Instant::now(),
+            resumed_elapsed: Duration::ZERO,
+            interruptible: checkpointer.is_some(),
+        };
+        search.best.offer(level, Vec::new);
+        if !RuleReachability::analyse(level).can_win() {
+            search.stats.reached(0);
+            return Ok(SolveReport {
+                outcome: SolveOutcome::Unsolvable,
+                stats: search.stats,
+            });
+        }
+
+        let mut width = self.width;
+        let mut solution: Option<Vec<Move>> = None;
+        let saved = match &checkpointer {
+            Some(checkpointer) => checkpointer.load()?,
+            None => None,
+        };
+        if let Some(bytes) = saved {
+            let mut reader = Reader::for_level(&bytes, level);
+            search.stats = reader.stats()?;
+            width = reader.usize()?;
+            solution = match reader.varint()? {
+                0 => None,
+                _ => Some(reader.moves()?),
+            };
+        }
+        search.resumed_elapsed = search.stats.elapsed;
+
+        let outcome = loop {
+            search.update_elapsed();
+            if let Some(checkpointer) = &mut checkpointer {
+                let saving = Instant::now();
+                checkpointer
+                    .save_if_due(|writer| write_run(writer, &search.stats, width, &solution))?;
+                search.stats.add_time(Phase::Checkpoints, saving);
+            }
+
+            // Only the solutions shorter than the best one can improve it
+            let bound = solution
+                .as_ref()
+                .map_or(self.limits.max_depth, |moves| moves.len() - 1);
+            match self.run(&mut search, width, bound) {
+                Run::Solved(moves) if !self.improving => break SolveOutcome::Solved(moves),
+                Run::Solved(moves) => solution = Some(moves),
+                Run::Exhausted { dropped: true, .. } if width < self.max_width => (),
+                Run::Exhausted { dropped: true, .. } => match solution {
+                    Some(moves) => break SolveOutcome::Solved(moves),
+                    None => {
+                        break SolveOutcome::LimitReached {
+                            limit: Limit::Width,
+                            best: search.best.into_partial(|moves| moves),
+                        }
+                    }
+                },
+                Run::Exhausted { cut, .. } => match solution {
+                    Some(moves) => break SolveOutcome::Solved(moves),
+                    None if cut => {
+                        break SolveOutcome::LimitReached {
+                            limit: Limit::Depth,
+                            best: search.best.into_partial(|moves| moves),
+                        }
+                    }
+                    None => break SolveOutcome::Unsolvable,
+                },
+                Run::Stopped(stop) => {
+                    // Saving the run to start over when resuming
+                    if let Some(checkpointer) = &mut checkpointer {
+                        search.update_elapsed();
+                        checkpointer
+                            .save(|writer| write_run(writer, &search.stats, width, &solution))?;
+                    }
+                    match (stop, solution) {
+                        (Stop::Interrupted, _) => return Err(interruption()),
+                        (Stop::Limit(_), Some(moves)) => break SolveOutcome::Solved(moves),
+                        (Stop::Limit(limit), None) => {
+                            break SolveOutcome::LimitReached {
+                                limit,
+                                best: search.best.into_partial(|moves| moves),
+                            }
+                        }
+                    }
+                }
+            }
+            width = width.saturating_mul(2).min(self.max_width);
+        };
+        search.stats.elapsed = search.resumed_elapsed + search.start.elapsed();
+        Ok(SolveReport {
+            outcome,
+            stats: search.stats,
+        })
+    }
+
+    /// Runs the beam up to `bound` moves
+    fn run(&self, search: &mut Search, width: usize, bound: usize) -> Run {
+        let level = search.level;
+        let stats = &mut search.stats;
+        stats.reached(0);
+        let mut visited = HashSet::new();
+        visited.insert(state_key(level));
+        // (moves, state)
+        let mut layer = vec![(vec![], state_key(level))];
+        let mut dropped = false;
+
+        for depth in 0..bound {
+            // (estimate, moves, state)
+            let mut next_layer = vec![];
+            for (moves, key) in &layer {
+                stats.elapsed = search.resumed_elapsed + search.start.elapsed();
+                stats.memory(search.budget.memory(visited.len()));
+                search.reporter.report(stats);
+                if search.interruptible && interrupted() {
+                    return Run::Stopped(Stop::Interrupted);
+                }
+                if let Some(limit) = search.budget.exceeded(stats.expanded, visited.len()) {
+                    return Run::Stopped(Stop::Limit(limit));
+                }
+                stats.expanded += 1;
+                let current = stats.time(Phase::Expansion, || level_from_key(level, key));
+                for &m in MOVES.iter() {
+                    let mut next = current.clone();
+                    let game_state = stats.time(Phase::Expansion, || next.apply_move(m));
+                    stats.generated += 1;
+                    let mut next_moves = moves.clone();
+                    next_moves.push(m);
+                    if game_state == Some(EndState::Win) {
+                        return Run::Solved(next_moves);
+                    }
+                    let next_key = state_key(&next);
+                    if !stats.time(Phase::Duplicates, || visited.insert(next_key.clone())) {
+                        stats.duplicates += 1;
+                        continue;
+                    }
+                    stats.reached(depth + 1);
+                    if game_state == Some(EndState::Defeat) {
+                        continue;
+                    }
+                    let deadlocks = &search.deadlocks;
+                    if stats.time(Phase::Deadlocks, || deadlocks.is_deadlocked(&next)) {
+                        stats.pruned += 1;
+                        continue;
+                    }
+                    search.best.offer(&next, || next_moves.clone());
+                    let estimate = stats.time(Phase::Heuristic, || self.heuristic.estimate(&next));
+                    next_layer.push((estimate, next_moves, next_key));
+                }
+            }
+            if next_layer.is_empty() {
+                return Run::Exhausted {
+                    dropped,
+                    cut: false,
+                };
+            }
+
+            stats.time(Phase::Sorting, || {
+                next_layer.sort_by_key(|(estimate, _, _)| *estimate)
+            });
+            if next_layer.len() > width {
+                next_layer.truncate(width);
+                dropped = true;
+            }
+            layer = next_layer
+                .into_iter()
+                .map(|(_, moves, key)| (moves, key))
+                .collect();
+        }
+        Run::Exhausted { dropped, cut: true }
+    }
+}
+
+impl Search<'_> {
+    fn update_elapsed(&mut self) {
+        self.stats.elapsed = self.resumed_elapsed + self.start.elapsed();
+    }
+}
+
+/// Writes the run to start from when resuming, with the best solution so far
+fn write_run(writer: &mut Writer, stats: &SearchStats, width: usize, solution: &Option<Vec<Move>>) {
+    writer.stats(stats);
+    writer.varint(width as u64);
+    match solution {
+        Some(moves) => {
+            writer.varint(1);
+            writer.moves(moves);
+        }
+        None => writer.varint(0),
+    }
+}
+
+#[test]
+fn widens_the_beam_until_a_solution() {
+    use crate::solver::heuristic::{Blind, WinDistance};
+
+    let level = crate::levels_list::LEVELS_LIST[0].clone();
+    let report = BeamSearch::new(WinDistance, 1, 100)
+        .with_max_width(DEFAULT_BEAM_WIDTH)
+        .solve(&level);
+    let solution = report.outcome.solution().expect("Level 1 has a solution");
+    assert_eq!(
+        level.clone().apply_move_sequence(solution),
+        Some(EndState::Win)
+    );
+
+    // Without guidance, a narrow beam is widened until it keeps the shortest path
+    let report = BeamSearch::new(Blind, 2, 20)
+        .with_max_width(1024)
+        .improving()
+        .solve(&level);
+    assert_eq!(report.outcome.solution().map(|moves| moves.len()), Some(8));
+    // It stops at its width otherwise
+    let report = BeamSearch::new(Blind, 2, 20).solve(&level);
+    assert!(matches!(
+        report.outcome,
+        SolveOutcome::LimitReached {
+            limit: Limit::Width,
+            ..
+        }
+    ));
+}
@@ -0,0 +1,198 @@
+//! A breadth first solver, the first solution found is a shortest one
+
+use std::collections::VecDeque;
+use std::io;
+use std::time::Instant;
+
+use crate::interpreter::*;
+use crate::level::Level;
+use crate::solver::checkpoint::*;
+use crate::solver::deadlock::Deadlocks;
+use crate::solver::limits::*;
+use crate::solver::reachability::RuleReachability;
+use crate::solver::stats::*;
+use crate::solver::symmetry::Symmetries;
+use crate::solver::*;
+
+pub struct Bfs {
+    limits: Limits,
+    progress: Option<Progress>,
+    symmetries: bool,
+}
+
+impl Bfs {
+    /// Creates a solver giving up after `max_depth` moves
+    pub fn new(max_depth: usize) -> Self {
+        Self {
+            limits: Limits::depth(max_depth),
+            progress: None,
+            symmetries: true,
+        }
+    }
+
+    /// Replaces the limits of the search, including the maximum depth
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Reports the statistics of the search while it runs
+    pub fn with_progress(mut self, progress: Progress) -> Self {
+        self.progress = Some(progress);
+        self
+    }
+
+    /// Explores every state, even the ones equivalent by a symmetry of the level
+    pub fn without_symmetries(mut self) -> Self {
+        self.symmetries = false;
+        self
+    }
+
+    /// Searches a shortest winning move sequence
+    pub fn solve(&self, level: &Level) -> SolveReport {
+        self.search(level, None)
+            .expect("No I/O without checkpoints")
+    }
+
+    /// Searches a shortest winning move sequence, saving checkpoints of the search.
+    /// A search stopped at a limit saves a checkpoint it can be resumed from
+    /// with greater limits
+    pub fn solve_with_checkpoints(
+        &self,
+        level: &Level,
+        config: CheckpointConfig,
+    ) -> io::Result<SolveReport> {
+        self.search(level, Some(Checkpointer::new(config, level, "bfs")))
+    }
+
+    pub(crate) fn search(
+        &self,
+        level: &Level,
+        mut checkpointer: Option<Checkpointer>,
+    ) -> io::Result<SolveReport> {
+        // The tree holds the canonical keys, the frontier the concrete states
+        let symmetries = if self.symmetries {
+            Symmetries::new(level)
+        } else {
+            Symmetries::none()
+        };
+        let saved = match &checkpointer {
+            Some(checkpointer) => checkpointer.load()?,
+            None => None,
+        };
+        let (mut stats, mut tree, mut frontier) = match saved {
+            Some(bytes) => {
+                let mut reader = Reader::for_level(&bytes, level);
+                let stats = reader.stats()?;
+                let tree = reader.tree()?;
+                let mut frontier = VecDeque::new();
+                for _ in 0..reader.usize()? {
+                    frontier.push_back((reader.usize()?, reader.usize()?, reader.key()?));
+                }
+                (stats, tree, frontier)
+            }
+            None => {
+                let mut frontier = VecDeque::new();
+                frontier.push_back((0, 0, state_key(level)));
+                let mut stats = SearchStats::default();
+                stats.reached(0);
+                (
+                    stats,
+                    SearchTree::new(symmetries.canonical_key(level)),
+                    frontier,
+                )
+            }
+        };
+        if !RuleReachability::analyse(level).can_win() {
+            return Ok(SolveReport {
+                outcome: SolveOutcome::Unsolvable,
+                stats,
+            });
+        }
+        let (start, resumed_elapsed) = (Instant::now(), stats.elapsed);
+        let budget = Budget::new(&self.limits, level);
+        let mut reporter = Reporter::new(&self.progress);
+        let mut best = Best::default();
+        best.offer(level, || 0);
+        let deadlocks = Deadlocks::new(level);
+
+        loop {
+            stats.elapsed = resumed_elapsed + start.elapsed();
+            stats.memory(budget.memory(tree.len()));
+            reporter.report(&stats);
+            let stop = match frontier.front() {
+                None => {
+                    return Ok(SolveReport {
+                        outcome: SolveOutcome::Unsolvable,
+                        stats,
+                    })
+                }
+                Some(&(_, depth, _)) if depth >= self.limits.max_depth => Some(Limit::Depth),
+                Some(_) => budget.exceeded(stats.expanded, tree.len()),
+            };
+
+            let write = |writer: &mut Writer| {
+                writer.stats(&stats);
+                writer.tree(&tree);
+                writer.varint(frontier.len() as u64);
+                for (node, depth, key) in &frontier {
+                    writer.varint(*node as u64);
+                    writer.varint(*depth as u64);
+                    writer.key(key);
+                }
+            };
+            if let Some(checkpointer) = &mut checkpointer {
+                let saving = Instant::now();
+                if stop.is_some() {
+                    checkpointer.save(write)?;
+                } else {
+                    checkpointer.save_if_due(write)?;
+                }
+                stats.add_time(Phase::Checkpoints, saving);
+            }
+            if let Some(limit) = stop {
+                let best = best.into_partial(|node| tree.path(node));
+                return Ok(SolveReport {
+                    outcome: SolveOutcome::LimitReached { limit, best },
+                    stats,
+                });
+            }
+
+            let (node, depth, key) = frontier.pop_front().expect("The frontier isn't empty");
+            stats.expanded += 1;
+            let current = stats.time(Phase::Expansion, || level_from_key(level, &key));
+            for &m in MOVES.iter() {
+                let mut next = current.clone();
+                let game_state = stats.time(Phase::Expansion, || next.apply_move(m));
+                stats.generated += 1;
+                let inserted = stats.time(Phase::Duplicates, || {
+                    tree.insert(symmetries.canonical_key(&next), Some((node, m)))
+                });
+                let child = match inserted {
+                    Some(child) => child,
+                    None => {
+                        stats.duplicates += 1;
+                        continue;
+                    }
+                };
+                stats.reached(depth + 1);
+                match game_state {
+                    Some(EndState::Win) => {
+                        return Ok(SolveReport {
+                            outcome: SolveOutcome::Solved(tree.path(child)),
+                            stats,
+                        })
+                    }
+                    Some(EndState::Defeat) => (),
+                    None if stats.time(Phase::Deadlocks, || deadlocks.is_deadlocked(&next)) => {
+                        stats.pruned += 1;
+                    }
+                    None => {
+                        best.offer(&next, || child);
+                        frontier.push_back((child, depth + 1, state_key(&next)));
+                    }
+                }
+            }
+        }
+    }
+}
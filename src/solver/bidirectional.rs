@@ -0,0 +1,617 @@
+//! A bidirectional solver for the levels whose rules can never change
+//! Such a level is a pure movement puzzle: a backward search from the winning
+//! configurations meets a forward search from the start. When the rules can
+//! change, when several units are tagged YOU or when YOU can reach a unit tagged PUSH,
+//! it falls back to a forward search
+//!
+//! The backward search starts from the start level where the unit tagged YOU is moved
+//! on each square tagged WIN, the only winning states when nothing can be pushed.
+//! The maximum depth bounds the forward search alone, as in the breadth first solver,
+//! and the backward search stops once its states could only lead to longer solutions
+//!
+//! The checkpoints are saved between the expansions of two layers. An interruption
+//! stops the search in the middle of a layer, the last checkpoint is then kept
+
+use std::collections::VecDeque;
+use std::io;
+use std::time::{Duration, Instant};
+
+use crate::interpreter::*;
+use crate::level::*;
+use crate::solver::bfs::Bfs;
+use crate::solver::checkpoint::*;
+use crate::solver::deadlock::Deadlocks;
+use crate::solver::limits::*;
+use crate::solver::reachability::RuleReachability;
+use crate::solver::stats::*;
+use crate::solver::*;
+use crate::square::*;
+
+pub struct Bidirectional {
+    limits: Limits,
+    progress: Option<Progress>,
+}
+
+/// A side of the search, its tree and the states of its last layer
+struct Side {
+    tree: SearchTree,
+    layer: VecDeque<(usize, StateKey)>,
+    depth: usize,
+}
+
+/// The statistics and the limits of a search, with its best forward state
+struct Tracking {
+    budget: Budget,
+    stats: SearchStats,
+    best: Best<usize>,
+    /// Only the forward states are pruned
+    deadlocks: Deadlocks,
+    reporter: Reporter,
+    start: Instant,
+    resumed_elapsed: Duration,
+    /// Stops the search when the process is interrupted
+    interruptible: bool,
+}
+
+impl Bidirectional {
+    /// Creates a solver giving up after `max_depth` moves
+    pub fn new(max_depth: usize) -> Self {
+        Self {
+            limits: Limits::depth(max_depth),
+            progress: None,
+        }
+    }
+
+    /// Replaces the limits of the search, including the maximum depth
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Reports the statistics of the search while it runs
+    pub fn with_progress(mut self, progress: Progress) -> Self {
+        self.progress = Some(progress);
+        self
+    }
+
+    /// Returns if the bidirectional search applies to the level, otherwise
+    /// the solver falls back to a forward search. The winning states of a level
+    /// with units to push are too many to start a backward search from
+    pub fn applies(level: &Level) -> bool {
+        if !rules_are_fixed(level) || level.units_with_property(TYOU).len() != 1 {
+            return false;
+        }
+        let reached = reachable_squares(level);
+        level
+            .units_with_property(TPUSH)
+            .iter()
+            .all(|&(_, pos)| !reached[pos])
+    }
+
+    /// Searches a winning move sequence
+    pub fn solve(&self, level: &Level) -> SolveReport {
+        self.search(level, None)
+            .expect("No I/O without checkpoints")
+    }
+
+    /// Searches a winning move sequence, saving checkpoints of the search.
+    /// A search stopped at a limit between two layers saves a checkpoint it
+    /// can be resumed from with greater limits, one stopped in the middle of
+    /// a layer keeps its last checkpoint. The checkpoints of the forward search
+    /// it falls back to are the ones of the breadth first solver
+    pub fn solve_with_checkpoints(
+        &self,
+        level: &Level,
+        config: CheckpointConfig,
+    ) -> io::Result<SolveReport> {
+        let solver = if Self::applies(level) {
+            "bidirectional"
+        } else {
+            "bfs"
+        };
+        self.search(level, Some(Checkpointer::new(config, level, solver)))
+    }
+
+    fn search(
+        &self,
+        level: &Level,
+        mut checkpointer: Option<Checkpointer>,
+    ) -> io::Result<SolveReport> {
+        if !Self::applies(level) {
+            let mut bfs = Bfs::new(0).with_limits(self.limits.clone());
+            if let Some(progress) = &self.progress {
+                bfs = bfs.with_progress(progress.clone());
+            }
+            return bfs.search(level, checkpointer);
+        }
+        if !RuleReachability::analyse(level).can_win() {
+            return Ok(SolveReport {
+                outcome: SolveOutcome::Unsolvable,
+                stats: SearchStats::default(),
+            });
+        }
+
+        let saved = match &checkpointer {
+            Some(checkpointer) => checkpointer.load()?,
+            None => None,
+        };
+        let (stats, mut forward, mut backward) = match saved {
+            Some(bytes) => {
+                let mut reader = Reader::for_level(&bytes, level);
+                (
+                    reader.stats()?,
+                    Side::read(&mut reader)?,
+                    Side::read(&mut reader)?,
+                )
+            }
+            None => {
+                let mut stats = SearchStats::default();
+                stats.reached(0);
+                (
+                    stats,
+                    Side::new(vec![state_key(level)]),
+                    Side::new(goals(level)),
+                )
+            }
+        };
+        let mut tracking = Tracking {
+            budget: Budget::new(&self.limits, level),
+            resumed_elapsed: stats.elapsed,
+            stats,
+            best: Best::default(),
+            deadlocks: Deadlocks::new(level),
+            reporter: Reporter::new(&self.progress),
+            start: Instant::now(),
+            interruptible: checkpointer.is_some(),
+        };
+        tracking.best.offer(level, || 0);
+
+        loop {
+            // The forward search reached every state without winning
+            if forward.layer.is_empty() {
+                return Ok(SolveReport {
+                    outcome: SolveOutcome::Unsolvable,
+                    stats: tracking.stats,
+                });
+            }
+            let stop = if forward.depth >= self.limits.max_depth {
+                Some(Limit::Depth)
+            } else {
+                tracking.exceeded(forward.tree.len() + backward.tree.len())
+            };
+
+            let saving = Instant::now();
+            let stats = &tracking.stats;
+            let write = |writer: &mut Writer| {
+                writer.stats(stats);
+                forward.write(writer);
+                backward.write(writer);
+            };
+            if let Some(checkpointer) = &mut checkpointer {
+                if stop.is_some() {
+                    checkpointer.save(write)?;
+                } else {
+                    checkpointer.save_if_due(write)?;
+                }
+            }
+            tracking.stats.add_time(Phase::Checkpoints, saving);
+
+            // Expanding the smallest layer first, the backward one only while
+            // meeting it can lead to a solution within the maximum depth
+            let backward_useful = !backward.layer.is_empty()
+                && forward.depth + backward.depth < self.limits.max_depth;
+            let expansion = match stop {
+                Some(limit) => Err(Stop::Limit(limit)),
+                None if !backward_useful || forward.layer.len() <= backward.layer.len() => {
+                    self.expand_forward(level, &mut forward, &backward, &mut tracking)
+                }
+                None => self.expand_backward(level, &mut backward, &forward, &mut tracking),
+            };
+
+            let outcome = match expansion {
+                Ok(Some(moves)) => SolveOutcome::Solved(truncate_at_win(level, moves)),
+                Ok(None) => continue,
+                // The trees hold a part of the layer, they can't be saved
+                Err(Stop::Interrupted) => return Err(interruption()),
+                Err(Stop::Limit(limit)) => SolveOutcome::LimitReached {
+                    limit,
+                    best: tracking.best.into_partial(|node| forward.tree.path(node)),
+                },
+            };
+            return Ok(SolveReport {
+                outcome,
+                stats: tracking.stats,
+            });
+        }
+    }
+
+    /// Expands the forward layer, returns the shortest solution joining a state already
+    /// reached backward, or the interruption or the limit hit in the middle of the layer
+    fn expand_forward(
+        &self,
+        level: &Level,
+        forward: &mut Side,
+        backward: &Side,
+        tracking: &mut Tracking,
+    ) -> Result<Option<Vec<Move>>, Stop> {
+        let depth = forward.depth + 1;
+        // The backward tree holds states of several depths, the first meeting of the
+        // layer isn't always the shortest one
+        let mut shortest: Option<Vec<Move>> = None;
+        for (node, key) in forward.next_layer() {
+            if let Err(stop) = tracking.check(forward.tree.len() + backward.tree.len()) {
+                return shortest.map(Some).ok_or(stop);
+            }
+            let stats = &mut tracking.stats;
+            stats.expanded += 1;
+            let current = stats.time(Phase::Expansion, || level_from_key(level, &key));
+            for &m in MOVES.iter() {
+                let mut next = current.clone();
+                let game_state = stats.time(Phase::Expansion, || next.apply_move(m));
+                let next_key = state_key(&next);
+                stats.generated += 1;
+                let inserted = stats.time(Phase::Duplicates, || {
+                    forward.tree.insert(next_key.clone(), Some((node, m)))
+                });
+                let child = match inserted {
+                    Some(child) => child,
+                    None => {
+                        stats.duplicates += 1;
+                        continue;
+                    }
+                };
+                stats.reached(depth);
+                // No meeting of the layer is shorter than a win
+                if game_state == Some(EndState::Win) {
+                    return Ok(Some(forward.tree.path(child)));
+                }
+                if let Some(meeting) = backward.tree.get(&next_key) {
+                    // The backward states can be deeper than the remaining moves
+                    let moves = join(forward, child, backward, meeting);
+                    if moves.len() <= self.limits.max_depth {
+                        shortest = shorter(shortest, moves);
+                    }
+                }
+                if game_state.is_some() {
+                    continue;
+                }
+                let deadlocks = &tracking.deadlocks;
+                if stats.time(Phase::Deadlocks, || deadlocks.is_deadlocked(&next)) {
+                    stats.pruned += 1;
+                } else {
+                    tracking.best.offer(&next, || child);
+                    forward.layer.push_back((child, next_key));
+                }
+            }
+        }
+        Ok(shortest)
+    }
+
+    /// Expands the backward layer, returns the shortest solution joining a state already
+    /// reached forward, or the interruption or the limit hit in the middle of the layer
+    fn expand_backward(
+        &self,
+        level: &Level,
+        backward: &mut Side,
+        forward: &Side,
+        tracking: &mut Tracking,
+    ) -> Result<Option<Vec<Move>>, Stop> {
+        // The forward tree holds states of several depths
+        let mut shortest: Option<Vec<Move>> = None;
+        for (node, key) in backward.next_layer() {
+            if let Err(stop) = tracking.check(forward.tree.len() + backward.tree.len()) {
+                return shortest.map(Some).ok_or(stop);
+            }
+            let stats = &mut tracking.stats;
+            stats.expanded += 1;
+            let current = stats.time(Phase::Expansion, || level_from_key(level, &key));
+            for &m in MOVES.iter() {
+                for previous in stats.time(Phase::Expansion, || predecessors(&current, m)) {
+                    let previous_key = state_key(&previous);
+                    stats.generated += 1;
+                    let inserted = stats.time(Phase::Duplicates, || {
+                        backward.tree.insert(previous_key.clone(), Some((node, m)))
+                    });
+                    let parent = match inserted {
+                        Some(parent) => parent,
+                        None => {
+                            stats.duplicates += 1;
+                            continue;
+                        }
+                    };
+                    if let Some(meeting) = forward.tree.get(&previous_key) {
+                        shortest = shorter(shortest, join(forward, meeting, backward, parent));
+                    }
+                    backward.layer.push_back((parent, previous_key));
+                }
+            }
+        }
+        Ok(shortest)
+    }
+}
+
+impl Tracking {
+    /// Updates the statistics and reports them, then returns the first limit hit
+    fn exceeded(&mut self, states: usize) -> Option<Limit> {
+        self.stats.elapsed = self.resumed_elapsed + self.start.elapsed();
+        self.stats.memory(self.budget.memory(states));
+        self.reporter.report(&self.stats);
+        self.budget.exceeded(self.stats.expanded, states)
+    }
+
+    /// Returns why the search must stop in the middle of a layer
+    fn check(&mut self, states: usize) -> Result<(), Stop> {
+        if self.interruptible && interrupted() {
+            return Err(Stop::Interrupted);
+        }
+        match self.exceeded(states) {
+            Some(limit) => Err(Stop::Limit(limit)),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Side {
+    fn new(roots: Vec<StateKey>) -> Self {
+        let mut tree = SearchTree::default();
+        let layer = roots
+            .into_iter()
+            .filter_map(|key| tree.insert(key.clone(), None).map(|node| (node, key)))
+            .collect();
+        Self {
+            tree,
+            layer,
+            depth: 0,
+        }
+    }
+
+    fn read(reader: &mut Reader) -> io::Result<Self> {
+        let tree = reader.tree()?;
+        let mut layer = VecDeque::new();
+        for _ in 0..reader.usize()? {
+            layer.push_back((reader.usize()?, reader.key()?));
+        }
+        Ok(Self {
+            tree,
+            layer,
+            depth: reader.usize()?,
+        })
+    }
+
+    fn write(&self, writer: &mut Writer) {
+        writer.tree(&self.tree);
+        writer.varint(self.layer.len() as u64);
+        for (node, key) in &self.layer {
+            writer.varint(*node as u64);
+            writer.key(key);
+        }
+        writer.varint(self.depth as u64);
+    }
+
+    /// Takes the states of the last layer to expand them
+    fn next_layer(&mut self) -> VecDeque<(usize, StateKey)> {
+        self.depth += 1;
+        std::mem::take(&mut self.layer)
+    }
+}
+
+/// The start level with the unit tagged YOU moved on each square tagged WIN
+fn goals(level: &Level) -> Vec<StateKey> {
+    let (you, start) = level.units_with_property(TYOU)[0];
+    level
+        .units_with_property(TWIN)
+        .iter()
+        .map(|&(_, win)| {
+            let mut goal = level.clone();
+            goal.remove_layer_internal(you, start);
+            goal.add_layer_internal(you, win);
+            state_key(&goal)
+        })
+        .collect()
+}
+
+/// Returns the levels from which the given move leads to the given level.
+/// The unit tagged YOU is pulled back along with any number of the units it
+/// could have pushed, then each candidate is checked by playing the move
+fn predecessors(level: &Level, m: Move) -> Vec<Level> {
+    let (you, pos) = level.units_with_property(TYOU)[0];
+    let grid = &level.grid;
+    let start = match grid.apply_move(pos, opposite(m)) {
+        Some(start) => start,
+        None => return vec![],
+    };
+
+    // The line of squares in front of the unit holding units tagged PUSH
+    let mut line = vec![pos];
+    while let Some(next) = grid.apply_move(*line.last().unwrap(), m) {
+        if !level.rules.square_has_property(grid[next], TPUSH) {
+            break;
+        }
+        line.push(next);
+    }
+
+    let mut candidates = vec![];
+    let mut candidate = level.clone();
+    candidate.remove_layer_internal(you, pos);
+    candidate.add_layer_internal(you, start);
+    for pushed in 0..line.len() {
+        if pushed > 0 {
+            // Pulling back the pushed units of one more square
+            let (from, to) = (line[pushed], line[pushed - 1]);
+            for layer in grid[from].into_iter() {
+                if level.rules[Entity::from(layer)][usize::from(TPUSH)] {
+                    candidate.remove_layer_internal(layer, from);
+                    candidate.add_layer_internal(layer, to);
+                }
+            }
+        }
+        candidate.update_rules();
+        let mut check = candidate.clone();
+        check.apply_move(m);
+        if candidate.end_state().is_none() && check.grid.squares() == level.grid.squares() {
+            candidates.push(candidate.clone());
+        }
+    }
+    candidates
+}
+
+fn opposite(m: Move) -> Move {
+    match m {
+        Move::Left => RIGHT,
+        Move::Right => LEFT,
+        Move::Up => DOWN,
+        Move::Down => UP,
+        Move::Wait => WAIT,
+    }
+}
+
+/// Joins the path to a forward node with the path from a backward node
+fn join(forward: &Side, forward_node: usize, backward: &Side, backward_node: usize) -> Vec<Move> {
+    let mut moves = forward.tree.path(forward_node);
+    // The backward tree links a state to the state its move leads to
+    let mut to_goal = backward.tree.path(backward_node);
+    to_goal.reverse();
+    moves.extend(to_goal);
+    moves
+}
+
+/// Keeps the shortest of two solutions, the first one on a tie
+fn shorter(shortest: Option<Vec<Move>>, moves: Vec<Move>) -> Option<Vec<Move>> {
+    match shortest {
+        Some(shortest) if shortest.len() <= moves.len() => Some(shortest),
+        _ => Some(moves),
+    }
+}
+
+/// Cuts a solution after its first winning move
+fn truncate_at_win(level: &Level, mut moves: Vec<Move>) -> Vec<Move> {
+    let mut level = level.clone();
+    if let Some(end) = moves
+        .iter()
+        .position(|&m| level.apply_move(m) == Some(EndState::Win))
+    {
+        moves.truncate(end + 1);
+    }
+    moves
+}
+
+/// A level whose rules are walled off from the unit tagged YOU
+#[cfg(test)]
+fn walled_rules_level() -> Level {
+    let mut level = Level::new(9, 7);
+    level.add_rule(&[TBABA, TIS, TYOU], (0, 0), HORIZONTAL);
+    level.add_rule(&[TFLAG, TIS, TWIN], (4, 0), HORIZONTAL);
+    level.add_rule(&[TWALL, TIS, TSTOP], (0, 1), HORIZONTAL);
+    level.add_rule(&[TROCK, TIS, TPUSH], (4, 1), HORIZONTAL);
+    level.add_square_line(Entity::WALL.into(), (0, 2), 9, HORIZONTAL);
+    level.add_square_line(Entity::WALL.into(), (4, 3), 3, VERTICAL);
+    level.add_square(Entity::BABA.into(), (1, 4));
+    level.add_square(Entity::FLAG.into(), (7, 4));
+    level
+}
+
+#[test]
+fn meets_the_forward_search() {
+    let level = walled_rules_level();
+    assert!(Bidirectional::applies(&level));
+    let shortest = Bfs::new(30)
+        .solve(&level)
+        .outcome
+        .solution()
+        .expect("The level has a solution");
+    let solution = Bidirectional::new(30)
+        .solve(&level)
+        .outcome
+        .solution()
+        .expect("The level has a solution");
+    assert_eq!(solution.len(), shortest.len());
+    assert_eq!(
+        level.clone().apply_move_sequence(solution),
+        Some(EndState::Win)
+    );
+
+    // A rock Baba can push leaves winning states out of the backward search
+    let mut rock = level;
+    rock.add_square(Entity::ROCK.into(), (2, 5));
+    assert!(!Bidirectional::applies(&rock));
+    let solution = Bidirectional::new(30)
+        .solve(&rock)
+        .outcome
+        .solution()
+        .expect("The level has a solution");
+    assert_eq!(solution.len(), shortest.len());
+}
+
+#[test]
+fn bounds_the_forward_depth_alone() {
+    let mut level = Level::new(8, 5);
+    level.add_rule(&[TBABA, TIS, TYOU], (0, 0), HORIZONTAL);
+    level.add_rule(&[TFLAG, TIS, TWIN], (4, 0), HORIZONTAL);
+    level.add_rule(&[TWALL, TIS, TSTOP], (0, 1), HORIZONTAL);
+    level.add_square_line(Entity::WALL.into(), (0, 2), 8, HORIZONTAL);
+    level.add_square(Entity::BABA.into(), (0, 3));
+    level.add_square(Entity::FLAG.into(), (2, 3));
+    assert!(Bidirectional::applies(&level));
+    assert_eq!(
+        Bidirectional::new(2).solve(&level).outcome.solution(),
+        Some(vec![RIGHT; 2])
+    );
+    assert_eq!(Bidirectional::new(1).solve(&level).outcome.solution(), None);
+}
+
+#[test]
+fn keeps_the_shortest_meeting() {
+    // A wall with a gap at each end, the flag is closer through the top one
+    let mut level = Level::new(9, 8);
+    level.add_rule(&[TBABA, TIS, TYOU], (0, 0), HORIZONTAL);
+    level.add_rule(&[TFLAG, TIS, TWIN], (4, 0), HORIZONTAL);
+    level.add_rule(&[TWALL, TIS, TSTOP], (0, 1), HORIZONTAL);
+    level.add_square_line(Entity::WALL.into(), (0, 2), 9, HORIZONTAL);
+    level.add_square_line(Entity::WALL.into(), (4, 4), 3, VERTICAL);
+    level.add_square(Entity::BABA.into(), (1, 6));
+    level.add_square(Entity::FLAG.into(), (7, 4));
+    assert!(Bidirectional::applies(&level));
+    let shortest = Bfs::new(30)
+        .solve(&level)
+        .outcome
+        .solution()
+        .expect("The level has a solution");
+    let solution = Bidirectional::new(30)
+        .solve(&level)
+        .outcome
+        .solution()
+        .expect("The level has a solution");
+    assert_eq!(solution.len(), shortest.len());
+    assert_eq!(
+        level.clone().apply_move_sequence(solution),
+        Some(EndState::Win)
+    );
+}
+
+#[test]
+fn falls_back_to_breadth_first_checkpoints() {
+    let level = crate::levels_list::LEVELS_LIST[0].clone();
+    assert!(!Bidirectional::applies(&level));
+    let path = std::env::temp_dir().join(format!("baba_fallback_{}", std::process::id()));
+    let config = CheckpointConfig {
+        path: path.clone(),
+        interval: Duration::from_secs(600),
+        resume: false,
+    };
+    let report = Bidirectional::new(3)
+        .solve_with_checkpoints(&level, config.clone())
+        .unwrap();
+    assert_eq!(report.outcome.solution(), None);
+    // The checkpoint is resumed by the breadth first solver, not as a bidirectional search
+    let resume = CheckpointConfig {
+        resume: true,
+        ..config
+    };
+    let solution = Bfs::new(20)
+        .solve_with_checkpoints(&level, resume)
+        .unwrap()
+        .outcome
+        .solution();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(solution.map(|moves| moves.len()), Some(8));
+}
@@ -0,0 +1,384 @@
+//! Certificates that a level has no solution
+//! A certificate is only emitted after exploring every state reachable from the
+//! start, without the pruning or the shortcuts of the solvers. It gives the number
+//! of reachable states, a hash of their sorted set and the rules active in at least
+//! one of them. The checker explores the states again with a traversal of its own,
+//! only sharing the game rules with the search, and compares the results
+//!
+//! A certificate is written as text:
+//! ```text
+//! unsolvability certificate
+//! level 83c2bd8e2a3ae0f1
+//! states 3
+//! state-hash 5b0a7c1e9f2d4a66
+//! rule BABA IS YOU
+//! ```
+
+use std::collections::{HashSet, VecDeque};
+use std::fmt;
+use std::io;
+
+use crate::encoding::*;
+use crate::interpreter::*;
+use crate::level::Level;
+use crate::solver::limits::*;
+use crate::solver::stats::*;
+use crate::solver::*;
+use crate::square::*;
+
+/// First line of a certificate
+const HEADER: &str = "unsolvability certificate";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Certificate {
+    pub level_hash: u64,
+    /// Number of states reachable from the start
+    pub states: usize,
+    /// Hash of the sorted set of the reachable states
+    pub state_hash: u64,
+    /// The rules active in at least one reachable state, the default ones included
+    pub rules: Vec<(Entity, Text)>,
+}
+
+/// Why a certificate doesn't hold
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CheckError {
+    /// The certificate was made for another level
+    OtherLevel,
+    /// A reachable state wins
+    Winnable,
+    States {
+        certified: usize,
+        found: usize,
+    },
+    StateHash,
+    Rules,
+    /// The check stopped before exploring every state
+    Stopped(Limit),
+}
+
+impl fmt::Display for CheckError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CheckError::OtherLevel => write!(f, "the certificate is for another level"),
+            CheckError::Winnable => write!(f, "the level can be won"),
+            CheckError::States { certified, found } => write!(
+                f,
+                "{} reachable states certified, {} found",
+                certified, found
+            ),
+            CheckError::StateHash => write!(f, "the hash of the reachable states differs"),
+            CheckError::Rules => write!(f, "the rules ever active differ"),
+            CheckError::Stopped(limit) => write!(f, "the check stopped at the {}", limit),
+        }
+    }
+}
+
+/// The outcome of a search, with a certificate when the level is unsolvable
+#[derive(Clone, Debug)]
+pub struct CertifyReport {
+    pub outcome: SolveOutcome,
+    pub stats: SearchStats,
+    pub certificate: Option<Certificate>,
+}
+
+/// Explores every reachable state to certify that a level has no solution
+pub struct Certifier {
+    limits: Limits,
+    progress: Option<Progress>,
+}
+
+impl Default for Certifier {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Certifier {
+    /// Creates a certifier with no limit, the reachable states are never cut by their depth
+    pub fn new() -> Self {
+        Self {
+            limits: Limits::depth(usize::MAX),
+            progress: None,
+        }
+    }
+
+    /// Replaces the limits of the search, the maximum depth is ignored
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Reports the statistics of the search while it runs
+    pub fn with_progress(mut self, progress: Progress) -> Self {
+        self.progress = Some(progress);
+        self
+    }
+
+    /// Explores the reachable states breadth first, a win found on the way is returned
+    pub fn certify(&self, level: &Level) -> CertifyReport {
+        let budget = Budget::new(&self.limits, level);
+        let mut reporter = Reporter::new(&self.progress);
+        let mut stats = SearchStats::default();
+        stats.reached(0);
+        let mut best = Best::default();
+        best.offer(level, || 0);
+        let mut rules = RulesSeen::default();
+        rules.add(level);
+        let mut tree = SearchTree::new(state_key(level));
+        // (node, depth, state)
+        let mut frontier = VecDeque::new();
+        frontier.push_back((0, 0, state_key(level)));
+
+        while let Some((node, depth, key)) = frontier.pop_front() {
+            stats.elapsed = budget.elapsed();
+            stats.memory(budget.memory(tree.len()));
+            reporter.report(&stats);
+            if let Some(limit) = budget.exceeded(stats.expanded, tree.len()) {
+                return CertifyReport {
+                    outcome: SolveOutcome::LimitReached {
+                        limit,
+                        best: best.into_partial(|node| tree.path(node)),
+                    },
+                    stats,
+                    certificate: None,
+                };
+            }
+
+            stats.expanded += 1;
+            let current = stats.time(Phase::Expansion, || level_from_key(level, &key));
+            for &m in MOVES.iter() {
+                let mut next = current.clone();
+                let game_state = stats.time(Phase::Expansion, || next.apply_move(m));
+                stats.generated += 1;
+                let next_key = state_key(&next);
+                let child = match stats.time(Phase::Duplicates, || {
+                    tree.insert(next_key.clone(), Some((node, m)))
+                }) {
+                    Some(child) => child,
+                    None => {
+                        stats.duplicates += 1;
+                        continue;
+                    }
+                };
+                stats.reached(depth + 1);
+                match game_state {
+                    Some(EndState::Win) => {
+                        stats.elapsed = budget.elapsed();
+                        return CertifyReport {
+                            outcome: SolveOutcome::Solved(tree.path(child)),
+                            stats,
+                            certificate: None,
+                        };
+                    }
+                    // A lost state is reachable but can't be left
+                    Some(EndState::Defeat) => rules.add(&next),
+                    None => {
+                        rules.add(&next);
+                        best.offer(&next, || child);
+                        frontier.push_back((child, depth + 1, next_key));
+                    }
+                }
+            }
+        }
+
+        stats.elapsed = budget.elapsed();
+        let certificate = Certificate {
+            level_hash: level_hash(level),
+            states: tree.len(),
+            state_hash: state_set_hash(tree.keys().cloned().collect()),
+            rules: rules.into_rules(),
+        };
+        CertifyReport {
+            outcome: SolveOutcome::Unsolvable,
+            stats,
+            certificate: Some(certificate),
+        }
+    }
+}
+
+impl Certificate {
+    /// Explores the reachable states again depth first and compares them with the certificate
+    pub fn check(&self, level: &Level, limits: &Limits) -> Result<(), CheckError> {
+        if level_hash(level) != self.level_hash {
+            return Err(CheckError::OtherLevel);
+        }
+        let budget = Budget::new(limits, level);
+        let mut rules = RulesSeen::default();
+        rules.add(level);
+        let mut seen = HashSet::new();
+        seen.insert(state_key(level));
+        let mut stack = vec![level.clone()];
+        let mut expanded = 0;
+        while let Some(current) = stack.pop() {
+            if let Some(limit) = budget.exceeded(expanded, seen.len()) {
+                return Err(CheckError::Stopped(limit));
+            }
+            expanded += 1;
+            for &m in MOVES.iter() {
+                let mut next = current.clone();
+                let game_state = next.apply_move(m);
+                if game_state == Some(EndState::Win) {
+                    return Err(CheckError::Winnable);
+                }
+                if !seen.insert(state_key(&next)) {
+                    continue;
+                }
+                rules.add(&next);
+                if game_state.is_none() {
+                    stack.push(next);
+                }
+            }
+        }
+
+        if seen.len() != self.states {
+            return Err(CheckError::States {
+                certified: self.states,
+                found: seen.len(),
+            });
+        }
+        if state_set_hash(seen.into_iter().collect()) != self.state_hash {
+            return Err(CheckError::StateHash);
+        }
+        if rules.into_rules() != self.rules {
+            return Err(CheckError::Rules);
+        }
+        Ok(())
+    }
+
+    /// Reads a certificate written by `Display`
+    pub fn parse(text: &str) -> io::Result<Self> {
+        let mut lines = text.lines().map(str::trim).filter(|line| !line.is_empty());
+        if lines.next() != Some(HEADER) {
+            return Err(invalid("not an unsolvability certificate"));
+        }
+        let mut field = |name: &str| {
+            lines
+                .next()
+                .and_then(|line| line.strip_prefix(name))
+                .and_then(|value| value.strip_prefix(' '))
+                .ok_or_else(|| invalid(&format!("missing {}", name)))
+        };
+        let hex = |value: &str| u64::from_str_radix(value, 16).map_err(|_| invalid("invalid hash"));
+        let level_hash = hex(field("level")?)?;
+        let states = field("states")?
+            .parse()
+            .map_err(|_| invalid("invalid number of states"))?;
+        let state_hash = hex(field("state-hash")?)?;
+        let rules = lines
+            .map(|line| {
+                line.strip_prefix("rule ")
+                    .and_then(parse_rule)
+                    .ok_or_else(|| invalid(&format!("invalid rule: {}", line)))
+            })
+            .collect::<io::Result<_>>()?;
+        Ok(Self {
+            level_hash,
+            states,
+            state_hash,
+            rules,
+        })
+    }
+}
+
+impl fmt::Display for Certificate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{}", HEADER)?;
+        writeln!(f, "level {:016x}", self.level_hash)?;
+        writeln!(f, "states {}", self.states)?;
+        writeln!(f, "state-hash {:016x}", self.state_hash)?;
+        for (entity, text) in &self.rules {
+            writeln!(f, "rule {:?} IS {}", entity, text)?;
+        }
+        Ok(())
+    }
+}
+
+/// Reads a rule written as `ENTITY IS TEXT`
+fn parse_rule(rule: &str) -> Option<(Entity, Text)> {
+    let mut words = rule.split(' ');
+    let (entity, is, text) = (words.next()?, words.next()?, words.next()?);
+    if is != "IS" || words.next().is_some() {
+        return None;
+    }
+    let entity = ENTITIES
+        .iter()
+        .cloned()
+        .find(|e| format!("{:?}", e) == entity)?;
+    let text = (0..TEXTS_NUMBER)
+        .map(Text::from)
+        .find(|t| t.to_string() == text)?;
+    Some((entity, text))
+}
+
+/// Hashes the states in increasing order
+fn state_set_hash(mut keys: Vec<StateKey>) -> u64 {
+    keys.sort();
+    let mut bytes = vec![];
+    for key in keys {
+        let encoded = encode_squares(&key);
+        write_varint(&mut bytes, encoded.len() as u64);
+        bytes.extend(encoded);
+    }
+    fnv1a(&bytes)
+}
+
+/// The rules active in the states seen so far
+#[derive(Default)]
+struct RulesSeen {
+    rules: Vec<(Entity, Text)>,
+}
+
+impl RulesSeen {
+    fn add(&mut self, level: &Level) {
+        for rule in level.rules.active_rules() {
+            if !self.rules.contains(&rule) {
+                self.rules.push(rule);
+            }
+        }
+    }
+
+    /// Returns the rules in the order of the entities then of the texts
+    fn into_rules(mut self) -> Vec<(Entity, Text)> {
+        self.rules
+            .sort_by_key(|&(entity, text)| (entity as u8, usize::from(text)));
+        self.rules
+    }
+}
+
+#[test]
+fn checks_a_certificate() {
+    use crate::level::*;
+
+    // Baba can only walk along the bottom row, there is nothing to win on
+    let mut level = Level::new(3, 2);
+    level.add_rule(&[TBABA, TIS, TYOU], (0, 0), HORIZONTAL);
+    level.add_square(Entity::BABA.into(), (1, 1));
+    let report = Certifier::new().certify(&level);
+    assert_eq!(report.outcome, SolveOutcome::Unsolvable);
+    let certificate = report.certificate.expect("The level is unsolvable");
+    assert_eq!(certificate.states, 3);
+    assert!(certificate.rules.contains(&(Entity::BABA, TYOU)));
+    assert_eq!(certificate.check(&level, &Limits::depth(0)), Ok(()));
+    assert_eq!(
+        Certificate::parse(&certificate.to_string()).ok(),
+        Some(certificate.clone())
+    );
+
+    let forged = Certificate {
+        states: 4,
+        ..certificate.clone()
+    };
+    assert_eq!(
+        forged.check(&level, &Limits::depth(0)),
+        Err(CheckError::States {
+            certified: 4,
+            found: 3
+        })
+    );
+    let level_1 = crate::levels_list::LEVELS_LIST[0].clone();
+    assert_eq!(
+        certificate.check(&level_1, &Limits::depth(0)),
+        Err(CheckError::OtherLevel)
+    );
+}
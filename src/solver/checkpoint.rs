@@ -0,0 +1,494 @@
+//! Checkpoints of the solvers, saved at intervals or when the process is interrupted
+//! A checkpoint starts with the version of its format, the hash of the level and the
+//! name of the solver, so it is never resumed by another version of the format, on
+//! another level or by another solver. The rest is written by
+//! each solver with the variable length integers of the state encoding
+
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::encoding::*;
+use crate::interpreter::*;
+use crate::level::Level;
+use crate::solver::limits::*;
+use crate::solver::stats::*;
+use crate::solver::*;
+
+const MAGIC: &[u8] = b"BABACKPT";
+/// Version of the checkpoint format, to increase when the layout of a solver changes
+const VERSION: u64 = 2;
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_interrupt(_signal: libc::c_int) {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+}
+
+/// Catches SIGINT so that the running solver saves a checkpoint before stopping
+pub fn catch_interruptions() {
+    let handler: extern "C" fn(libc::c_int) = on_interrupt;
+    // Safety: the handler only stores in an atomic boolean
+    unsafe {
+        libc::signal(libc::SIGINT, handler as libc::sighandler_t);
+    }
+}
+
+/// Returns if the process was interrupted since the interruptions are caught
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+/// Where and how often a solver saves its checkpoints
+#[derive(Clone, Debug)]
+pub struct CheckpointConfig {
+    pub path: PathBuf,
+    pub interval: Duration,
+    /// Continues from the checkpoint file instead of starting a new search
+    pub resume: bool,
+}
+
+/// Saves the checkpoints of a running search
+pub struct Checkpointer {
+    config: CheckpointConfig,
+    level_hash: u64,
+    solver: &'static str,
+    last_save: Instant,
+}
+
+impl Checkpointer {
+    pub fn new(config: CheckpointConfig, level: &Level, solver: &'static str) -> Self {
+        Self {
+            config,
+            level_hash: level_hash(level),
+            solver,
+            last_save: Instant::now(),
+        }
+    }
+
+    /// Loads the state written by the solver if the search is resumed
+    pub fn load(&self) -> io::Result<Option<Vec<u8>>> {
+        if !self.config.resume {
+            return Ok(None);
+        }
+        let bytes = fs::read(&self.config.path)?;
+        let mut reader = Reader::new(&bytes);
+        if reader.bytes(MAGIC.len())? != MAGIC {
+            return Err(invalid("not a checkpoint file"));
+        }
+        if reader.varint()? != VERSION {
+            return Err(invalid("the checkpoint was saved by another version"));
+        }
+        if reader.varint()? != self.level_hash {
+            return Err(invalid("the checkpoint was saved on another level"));
+        }
+        let solver_len = reader.varint()? as usize;
+        if reader.bytes(solver_len)? != self.solver.as_bytes() {
+            return Err(invalid("the checkpoint was saved by another solver"));
+        }
+        Ok(Some(reader.rest().to_vec()))
+    }
+
+    /// Saves the state written by the solver, the file is replaced atomically
+    pub fn save(&mut self, write: impl FnOnce(&mut Writer)) -> io::Result<()> {
+        let mut state = Writer::default();
+        write(&mut state);
+        let mut bytes = MAGIC.to_vec();
+        write_varint(&mut bytes, VERSION);
+        write_varint(&mut bytes, self.level_hash);
+        write_varint(&mut bytes, self.solver.len() as u64);
+        bytes.extend(self.solver.as_bytes());
+        bytes.extend(state.bytes);
+
+        let tmp_path = self.config.path.with_extension("tmp");
+        fs::write(&tmp_path, bytes)?;
+        fs::rename(tmp_path, &self.config.path)?;
+        self.last_save = Instant::now();
+        Ok(())
+    }
+
+    /// Saves the state written by the solver if the interval elapsed or the
+    /// process was interrupted, an interruption is then returned as an error
+    pub fn save_if_due(&mut self, write: impl FnOnce(&mut Writer)) -> io::Result<()> {
+        if interrupted() {
+            self.save(write)?;
+            return Err(interruption());
+        }
+        if self.last_save.elapsed() >= self.config.interval {
+            self.save(write)?;
+        }
+        Ok(())
+    }
+}
+
+/// Why a search stopped before its end
+pub enum Stop {
+    Interrupted,
+    Limit(Limit),
+}
+
+/// The error returned by a search stopped by an interruption
+pub fn interruption() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Interrupted,
+        "interrupted, the search can be resumed from its checkpoint",
+    )
+}
+
+/// Writes the state of a solver
+#[derive(Default)]
+pub struct Writer {
+    bytes: Vec<u8>,
+}
+
+impl Writer {
+    pub fn varint(&mut self, value: u64) {
+        write_varint(&mut self.bytes, value);
+    }
+
+    pub fn key(&mut self, key: &[Square]) {
+        let bytes = encode_squares(key);
+        self.varint(bytes.len() as u64);
+        self.bytes.extend(bytes);
+    }
+
+    pub fn move_(&mut self, m: Move) {
+        self.bytes.push(m as u8);
+    }
+
+    pub fn stats(&mut self, stats: &SearchStats) {
+        self.varint(stats.expanded);
+        self.varint(stats.generated);
+        self.varint(stats.duplicates);
+        self.varint(stats.pruned);
+        self.varint(stats.frontier_by_depth.len() as u64);
+        for &states in &stats.frontier_by_depth {
+            self.varint(states);
+        }
+        self.varint(stats.elapsed.as_micros() as u64);
+        for time in &stats.phases {
+            self.varint(time.as_micros() as u64);
+        }
+        self.varint(stats.peak_memory as u64);
+    }
+
+    pub fn moves(&mut self, moves: &[Move]) {
+        self.varint(moves.len() as u64);
+        for &m in moves {
+            self.move_(m);
+        }
+    }
+
+    pub fn tree<E: Edge>(&mut self, tree: &SearchTree<E>) {
+        self.varint(tree.nodes.len() as u64);
+        for parent in &tree.nodes {
+            match parent {
+                Some((node, edge)) => {
+                    self.varint(*node as u64 + 1);
+                    edge.write(self);
+                }
+                None => self.varint(0),
+            }
+        }
+        for (key, &node) in &tree.index {
+            self.varint(node as u64);
+            self.key(key);
+        }
+    }
+
+    pub fn path_tree(&mut self, tree: &PathTree) {
+        self.varint(tree.nodes.len() as u64);
+        for parent in &tree.nodes {
+            match parent {
+                Some((node, m)) => {
+                    self.varint(*node as u64 + 1);
+                    self.move_(*m);
+                }
+                None => self.varint(0),
+            }
+        }
+    }
+}
+
+/// Reads the state of a solver
+pub struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+    squares: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self {
+            bytes,
+            offset: 0,
+            squares: 0,
+        }
+    }
+
+    /// Creates a reader of states of the given level
+    pub fn for_level(bytes: &'a [u8], level: &Level) -> Self {
+        Self {
+            squares: level.grid.squares().len(),
+            ..Self::new(bytes)
+        }
+    }
+
+    fn bytes(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        let bytes = self
+            .bytes
+            .get(self.offset..self.offset + len)
+            .ok_or_else(|| invalid("truncated checkpoint"))?;
+        self.offset += len;
+        Ok(bytes)
+    }
+
+    fn rest(&self) -> &'a [u8] {
+        &self.bytes[self.offset..]
+    }
+
+    pub fn varint(&mut self) -> io::Result<u64> {
+        read_varint(self.bytes, &mut self.offset)
+    }
+
+    pub fn usize(&mut self) -> io::Result<usize> {
+        self.varint().map(|value| value as usize)
+    }
+
+    pub fn key(&mut self) -> io::Result<StateKey> {
+        let len = self.usize()?;
+        decode_squares(self.bytes(len)?, self.squares)
+    }
+
+    pub fn move_(&mut self) -> io::Result<Move> {
+        match self.bytes(1)?[0] {
+            0 => Ok(LEFT),
+            1 => Ok(RIGHT),
+            2 => Ok(UP),
+            3 => Ok(DOWN),
+            4 => Ok(WAIT),
+            _ => Err(invalid("invalid move in checkpoint")),
+        }
+    }
+
+    pub fn stats(&mut self) -> io::Result<SearchStats> {
+        let mut stats = SearchStats {
+            expanded: self.varint()?,
+            generated: self.varint()?,
+            duplicates: self.varint()?,
+            pruned: self.varint()?,
+            ..SearchStats::default()
+        };
+        for _ in 0..self.usize()? {
+            stats.frontier_by_depth.push(self.varint()?);
+        }
+        stats.elapsed = Duration::from_micros(self.varint()?);
+        for time in stats.phases.iter_mut() {
+            *time = Duration::from_micros(self.varint()?);
+        }
+        stats.peak_memory = self.usize()?;
+        Ok(stats)
+    }
+
+    pub fn moves(&mut self) -> io::Result<Vec<Move>> {
+        (0..self.usize()?).map(|_| self.move_()).collect()
+    }
+
+    pub fn tree<E: Edge>(&mut self) -> io::Result<SearchTree<E>> {
+        let mut tree = SearchTree::default();
+        for _ in 0..self.usize()? {
+            let parent = match self.usize()? {
+                0 => None,
+                node => Some((node - 1, E::read(self)?)),
+            };
+            tree.nodes.push(parent);
+        }
+        for _ in 0..tree.nodes.len() {
+            let node = self.usize()?;
+            tree.index.insert(self.key()?, node);
+        }
+        Ok(tree)
+    }
+
+    pub fn path_tree(&mut self) -> io::Result<PathTree> {
+        let mut tree = PathTree::default();
+        for _ in 0..self.usize()? {
+            let parent = match self.usize()? {
+                0 => None,
+                node => Some((node - 1, self.move_()?)),
+            };
+            tree.nodes.push(parent);
+        }
+        Ok(tree)
+    }
+}
+
+/// An edge of the search trees saved in the checkpoints
+pub trait Edge: Sized {
+    fn write(&self, writer: &mut Writer);
+    fn read(reader: &mut Reader) -> io::Result<Self>;
+}
+
+impl Edge for Move {
+    fn write(&self, writer: &mut Writer) {
+        writer.move_(*self);
+    }
+
+    fn read(reader: &mut Reader) -> io::Result<Self> {
+        reader.move_()
+    }
+}
+
+#[test]
+fn resumes_a_search_only_on_its_level() {
+    let level = crate::levels_list::LEVELS_LIST[0].clone();
+    let path = std::env::temp_dir().join(format!("baba_checkpoint_{}", std::process::id()));
+    let config = CheckpointConfig {
+        path: path.clone(),
+        interval: Duration::from_secs(600),
+        resume: false,
+    };
+    let resume = CheckpointConfig {
+        resume: true,
+        ..config.clone()
+    };
+
+    // Giving up at a small depth, then resuming with a greater one
+    let bfs = crate::solver::bfs::Bfs::new(3);
+    assert!(matches!(
+        bfs.solve_with_checkpoints(&level, config).unwrap().outcome,
+        SolveOutcome::LimitReached {
+            limit: Limit::Depth,
+            ..
+        }
+    ));
+    let mut other_level = level.clone();
+    other_level.apply_move(RIGHT);
+    let bfs = crate::solver::bfs::Bfs::new(20);
+    assert!(bfs
+        .solve_with_checkpoints(&other_level, resume.clone())
+        .is_err());
+    let saved = fs::read(&path).unwrap();
+    let mut old_version = saved.clone();
+    old_version[MAGIC.len()] = VERSION as u8 - 1;
+    fs::write(&path, old_version).unwrap();
+    assert!(bfs.solve_with_checkpoints(&level, resume.clone()).is_err());
+    fs::write(&path, saved).unwrap();
+    let solution = bfs
+        .solve_with_checkpoints(&level, resume)
+        .unwrap()
+        .outcome
+        .solution()
+        .unwrap();
+    fs::remove_file(&path).unwrap();
+
+    assert_eq!(solution.len(), 8);
+    assert_eq!(
+        level.clone().apply_move_sequence(solution),
+        Some(EndState::Win)
+    );
+}
+
+#[test]
+fn resumes_where_the_search_stopped() {
+    use crate::level::*;
+    use crate::solver::beam::BeamSearch;
+    use crate::solver::greedy::GreedyBestFirst;
+    use crate::solver::heuristic::WinDistance;
+    use crate::solver::macro_moves::MacroBfs;
+    use crate::solver::mcts::MonteCarlo;
+
+    let level = crate::levels_list::LEVELS_LIST[0].clone();
+    let path = std::env::temp_dir().join(format!("baba_resume_{}", std::process::id()));
+    let config = CheckpointConfig {
+        path: path.clone(),
+        interval: Duration::from_secs(600),
+        resume: false,
+    };
+    let resume = CheckpointConfig {
+        resume: true,
+        ..config.clone()
+    };
+    // The search stops at the node limit, then resumes to the solution found in one go
+    let check =
+        |level: &Level,
+         max_nodes,
+         solve: &dyn Fn(Limits, CheckpointConfig) -> io::Result<SolveReport>| {
+            let stopped = Limits {
+                max_nodes: Some(max_nodes),
+                ..Limits::depth(100)
+            };
+            let report = solve(stopped, config.clone()).unwrap();
+            assert!(matches!(
+                report.outcome,
+                SolveOutcome::LimitReached {
+                    limit: Limit::Nodes,
+                    ..
+                }
+            ));
+            let resumed = solve(Limits::depth(100), resume.clone()).unwrap();
+            assert!(resumed.stats.expanded >= report.stats.expanded);
+            let solution = resumed.outcome.solution().unwrap();
+            assert_eq!(
+                level.clone().apply_move_sequence(solution.clone()),
+                Some(EndState::Win)
+            );
+            solution
+        };
+
+    let greedy = |limits| GreedyBestFirst::new(WinDistance, 100).with_limits(limits);
+    assert_eq!(
+        check(&level, 1, &|limits, config| greedy(limits)
+            .solve_with_checkpoints(&level, config)),
+        greedy(Limits::depth(100))
+            .solve(&level)
+            .outcome
+            .solution()
+            .unwrap()
+    );
+    let beam = |limits| BeamSearch::new(WinDistance, 10, 100).with_limits(limits);
+    assert_eq!(
+        check(&level, 1, &|limits, config| beam(limits)
+            .solve_with_checkpoints(&level, config)),
+        beam(Limits::depth(100))
+            .solve(&level)
+            .outcome
+            .solution()
+            .unwrap()
+    );
+    // Level 1 takes a single macro move, pushing the rock onto the flag takes two
+    let mut pushes = Level::new(8, 4);
+    pushes.add_rule(&[TBABA, TIS, TYOU], (0, 0), HORIZONTAL);
+    pushes.add_rule(&[TFLAG, TIS, TWIN], (4, 0), HORIZONTAL);
+    pushes.add_rule(&[TWALL, TIS, TSTOP], (0, 1), HORIZONTAL);
+    pushes.add_rule(&[TROCK, TIS, TPUSH], (4, 1), HORIZONTAL);
+    pushes.add_square_line(Entity::WALL.into(), (0, 2), 8, HORIZONTAL);
+    pushes.add_square(Entity::BABA.into(), (0, 3));
+    pushes.add_square(Entity::ROCK.into(), (1, 3));
+    pushes.add_square(Entity::FLAG.into(), (2, 3));
+    let macro_bfs = |limits| MacroBfs::new(100).with_limits(limits);
+    assert_eq!(
+        check(&pushes, 1, &|limits, config| {
+            macro_bfs(limits).solve_with_checkpoints(&pushes, config)
+        }),
+        vec![RIGHT; 2]
+    );
+    // The random generator is saved, the resumed search makes the same moves
+    let mcts = |limits| {
+        MonteCarlo::new(WinDistance, 7, 100)
+            .with_rollout_depth(3)
+            .with_limits(limits)
+    };
+    assert_eq!(
+        check(&level, 10, &|limits, config| mcts(limits)
+            .solve_with_checkpoints(&level, config)),
+        mcts(Limits::depth(100))
+            .solve(&level)
+            .outcome
+            .solution()
+            .unwrap()
+    );
+    fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,175 @@
+//! Detection of the states where a pushed unit got stuck, as in Sokoban
+//! A unit tagged PUSH in a corner of walls can never be moved again, it then blocks
+//! the units tagged YOU like a wall. A state is a deadlock when such units cut every
+//! path to the units tagged WIN
+//!
+//! The analysis only holds while the rules stay the ones it was made with:
+//! a state is never a deadlock if its rules differ or if a text tile can be reached
+
+use crate::level::Level;
+use crate::rules::RuleManager;
+use crate::solver::MOVES;
+use crate::square::*;
+
+/// Dead squares of the pushed entities, precomputed from the static walls of a level
+pub struct Deadlocks {
+    rules: RuleManager,
+    /// Transformations could make walls disappear, nothing is pruned then
+    applies: bool,
+    /// Squares with a unit tagged STOP that never moves
+    walls: Vec<bool>,
+    /// Entity -> squares its units can never leave, empty if the entity isn't pushed
+    dead: Vec<Vec<bool>>,
+}
+
+impl Deadlocks {
+    /// Analyses the walls of the level under its current rules
+    pub fn new(level: &Level) -> Self {
+        let rules = &level.rules;
+        let is_wall = |entity: Entity| {
+            rules[entity][usize::from(TSTOP)]
+                && !rules[entity][usize::from(TPUSH)]
+                && !rules[entity][usize::from(TYOU)]
+        };
+        let walls: Vec<bool> = level
+            .grid
+            .squares()
+            .iter()
+            .map(|&square| square.into_iter().any(|layer| is_wall(Entity::from(layer))))
+            .collect();
+
+        let dead = ENTITIES
+            .iter()
+            .map(|&entity| {
+                if rules[entity][usize::from(TPUSH)] && !rules[entity][usize::from(TYOU)] {
+                    (0..walls.len())
+                        .map(|pos| is_corner(level, &walls, pos))
+                        .collect()
+                } else {
+                    vec![]
+                }
+            })
+            .collect();
+
+        Self {
+            rules: rules.clone(),
+            applies: !has_transformations(rules),
+            walls,
+            dead,
+        }
+    }
+
+    /// Returns if a unit of the entity on the square can never be pushed again
+    pub fn is_dead(&self, entity: Entity, pos: usize) -> bool {
+        self.dead[entity as usize]
+            .get(pos)
+            .cloned()
+            .unwrap_or(false)
+    }
+
+    /// Returns if no unit tagged YOU can ever reach a unit tagged WIN, because
+    /// of the walls and the stuck units
+    pub fn is_deadlocked(&self, level: &Level) -> bool {
+        if !self.applies || level.rules != self.rules {
+            return false;
+        }
+        let grid = &level.grid;
+        let blocked = |pos: usize| {
+            self.walls[pos]
+                || grid[pos]
+                    .into_iter()
+                    .any(|layer| self.is_dead(Entity::from(layer), pos))
+        };
+
+        let mut reached = vec![false; grid.squares().len()];
+        let mut stack: Vec<usize> = level
+            .units_with_property(TYOU)
+            .iter()
+            .map(|&(_, pos)| pos)
+            .collect();
+        while let Some(pos) = stack.pop() {
+            if reached[pos] {
+                continue;
+            }
+            reached[pos] = true;
+            let square = grid[pos];
+            // A unit tagged WIN can be reached, or a text tile that could change the rules
+            if level.rules.square_has_property(square, TWIN)
+                || square
+                    .into_iter()
+                    .any(|layer| matches!(layer, LayeredSquare::Text(_)))
+            {
+                return false;
+            }
+            for &m in MOVES.iter() {
+                if let Some(next) = grid.apply_move(pos, m) {
+                    if !reached[next] && !blocked(next) {
+                        stack.push(next);
+                    }
+                }
+            }
+        }
+        true
+    }
+}
+
+/// Returns if a pushed unit on the square is blocked by a wall or the border
+/// both horizontally and vertically. It can't be pushed along an axis where
+/// one side is blocked, as the pusher would have to stand on the other side
+fn is_corner(level: &Level, walls: &[bool], pos: usize) -> bool {
+    let grid = &level.grid;
+    let blocked = |next: Option<usize>| next.map_or(true, |next| walls[next]);
+    !walls[pos]
+        && (blocked(grid.left(pos)) || blocked(grid.right(pos)))
+        && (blocked(grid.up(pos)) || blocked(grid.down(pos)))
+}
+
+/// Returns if a rule transforms an entity into another one
+fn has_transformations(rules: &RuleManager) -> bool {
+    let entities = || {
+        ENTITIES
+            .iter()
+            .filter(|&&e| e != Entity::EMPTY && e != Entity::TEXT)
+    };
+    entities().any(|&entity| {
+        entities().any(|&target| target != entity && rules[entity][usize::from(Text::from(target))])
+    })
+}
+
+#[test]
+fn prunes_a_rock_stuck_in_front_of_the_flag() {
+    use crate::interpreter::*;
+    use crate::level::*;
+    use crate::solver::bfs::Bfs;
+    use crate::solver::limits::SolveOutcome;
+
+    // The rock ends in the corner above the only way to the flag
+    let mut level = Level::new(7, 6);
+    level.add_rule(&[TBABA, TIS, TYOU], (0, 0), HORIZONTAL);
+    level.add_rule(&[TFLAG, TIS, TWIN], (4, 0), HORIZONTAL);
+    level.add_rule(&[TWALL, TIS, TSTOP], (0, 1), HORIZONTAL);
+    level.add_rule(&[TROCK, TIS, TPUSH], (4, 1), HORIZONTAL);
+    level.add_square_line(Entity::WALL.into(), (0, 2), 7, HORIZONTAL);
+    level.add_square_line(Entity::WALL.into(), (0, 4), 5, HORIZONTAL);
+    level.add_square_line(Entity::WALL.into(), (0, 5), 5, HORIZONTAL);
+    level.add_square_line(Entity::WALL.into(), (6, 3), 3, VERTICAL);
+    level.add_square(Entity::BABA.into(), (0, 3));
+    level.add_square(Entity::ROCK.into(), (2, 3));
+    level.add_square(Entity::FLAG.into(), (5, 5));
+
+    let deadlocks = Deadlocks::new(&level);
+    assert!(deadlocks.is_dead(Entity::ROCK, level.grid.index((5, 3))));
+    assert!(!deadlocks.is_dead(Entity::ROCK, level.grid.index((4, 3))));
+    assert!(!deadlocks.is_deadlocked(&level));
+    let mut stuck = level.clone();
+    stuck.apply_move_sequence(vec![RIGHT; 4]);
+    assert!(deadlocks.is_deadlocked(&stuck));
+
+    let report = Bfs::new(20).solve(&level);
+    assert_eq!(report.outcome, SolveOutcome::Unsolvable);
+    assert!(report.stats.pruned > 0);
+
+    // The text tiles of level 1 can be reached, nothing is pruned
+    let level = crate::levels_list::LEVELS_LIST[0].clone();
+    assert!(!Deadlocks::new(&level).is_deadlocked(&level));
+}
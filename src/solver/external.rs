@@ -0,0 +1,428 @@
+//! An external-memory breadth first solver for the levels whose states don't fit in memory
+//! Each layer of the search is stored in a directory as a file of sorted encoded states.
+//! The successors of a layer are written in sorted runs of bounded size, then merged
+//! and compared to all the previous layers to remove the duplicates.
+//! A progress file records the last completed layer, so a run killed in the middle
+//! of a layer resumes from the start of this layer, and an interrupted run stops
+//! at the end of the current layer
+
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::PathBuf;
+use std::time::Instant;
+
+use crate::encoding::*;
+use crate::interpreter::*;
+use crate::level::Level;
+use crate::solver::checkpoint::*;
+use crate::solver::deadlock::Deadlocks;
+use crate::solver::limits::*;
+use crate::solver::reachability::RuleReachability;
+use crate::solver::stats::*;
+use crate::solver::*;
+
+/// Default number of states kept in memory before being written in a sorted run
+pub const DEFAULT_BATCH_SIZE: usize = 1 << 20;
+
+pub struct ExternalBfs {
+    dir: PathBuf,
+    limits: Limits,
+    progress: Option<Progress>,
+    batch_size: usize,
+}
+
+/// Reads the length-prefixed records of a file one by one
+struct RecordReader {
+    reader: BufReader<File>,
+}
+
+/// Merges sorted record files into a single sorted stream without duplicates
+struct Merge {
+    readers: Vec<RecordReader>,
+    heap: BinaryHeap<Reverse<(Vec<u8>, usize)>>,
+    last: Option<Vec<u8>>,
+}
+
+impl ExternalBfs {
+    /// Creates a solver working in the given directory and giving up after `max_depth` moves
+    pub fn new(dir: PathBuf, max_depth: usize, batch_size: usize) -> Self {
+        Self {
+            dir,
+            limits: Limits::depth(max_depth),
+            progress: None,
+            batch_size: batch_size.max(1),
+        }
+    }
+
+    /// Replaces the limits of the search, including the maximum depth.
+    /// The memory limit counts the states of the batch kept in memory
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Reports the statistics of the search while it runs, they only cover the
+    /// layers expanded since the run was resumed
+    pub fn with_progress(mut self, progress: Progress) -> Self {
+        self.progress = Some(progress);
+        self
+    }
+
+    /// Searches a shortest winning move sequence, resuming from the last
+    /// completed layer if the directory holds a run on the same level
+    pub fn solve(&self, level: &Level) -> io::Result<SolveReport> {
+        if !RuleReachability::analyse(level).can_win() {
+            return Ok(SolveReport {
+                outcome: SolveOutcome::Unsolvable,
+                stats: SearchStats::default(),
+            });
+        }
+        fs::create_dir_all(&self.dir)?;
+        let hash = level_hash(level);
+        let mut depth = match self.read_progress()? {
+            Some((progress_hash, depth)) if progress_hash == hash => depth,
+            Some(_) => return Err(invalid("the directory holds a run on another level")),
+            None => {
+                write_records(&self.layer_path(0), &[encode_squares(level.grid.squares())])?;
+                self.write_progress(hash, 0)?;
+                0
+            }
+        };
+
+        let budget = Budget::new(&self.limits, level);
+        let mut reporter = Reporter::new(&self.progress);
+        let mut stats = SearchStats::default();
+        if depth == 0 {
+            stats.reached(0);
+        }
+        let mut best = Best::default();
+        let deadlocks = Deadlocks::new(level);
+        while depth < self.limits.max_depth {
+            if interrupted() {
+                return Err(interruption());
+            }
+            let mut runs = vec![];
+            let mut batch = vec![];
+            let (generated, pruned) = (stats.generated, stats.pruned);
+            for record in RecordReader::open(&self.layer_path(depth))? {
+                stats.elapsed = budget.elapsed();
+                stats.memory(budget.memory(batch.len()));
+                reporter.report(&stats);
+                if let Some(limit) = budget.exceeded(stats.expanded, batch.len()) {
+                    for run in runs {
+                        fs::remove_file(run)?;
+                    }
+                    let best = self.best_partial(level, best)?;
+                    return Ok(SolveReport {
+                        outcome: SolveOutcome::LimitReached { limit, best },
+                        stats,
+                    });
+                }
+                stats.expanded += 1;
+                let record = record?;
+                let expansion = Instant::now();
+                let current = self.decode(level, &record)?;
+                best.offer(&current, || (record.clone(), depth));
+                let mut children = vec![];
+                for &m in MOVES.iter() {
+                    let mut next = current.clone();
+                    match next.apply_move(m) {
+                        Some(EndState::Win) => {
+                            let mut moves = self.path_to(level, depth, record)?;
+                            moves.push(m);
+                            stats.elapsed = budget.elapsed();
+                            return Ok(SolveReport {
+                                outcome: SolveOutcome::Solved(moves),
+                                stats,
+                            });
+                        }
+                        Some(EndState::Defeat) => (),
+                        None => {
+                            stats.generated += 1;
+                            children.push(next);
+                        }
+                    }
+                }
+                stats.add_time(Phase::Expansion, expansion);
+                for next in children {
+                    if stats.time(Phase::Deadlocks, || deadlocks.is_deadlocked(&next)) {
+                        stats.pruned += 1;
+                    } else {
+                        batch.push(encode_squares(next.grid.squares()));
+                    }
+                }
+                if batch.len() >= self.batch_size {
+                    let sorting = Instant::now();
+                    runs.push(self.write_run(runs.len(), &mut batch)?);
+                    stats.add_time(Phase::Sorting, sorting);
+                }
+            }
+            let sorting = Instant::now();
+            runs.push(self.write_run(runs.len(), &mut batch)?);
+            stats.add_time(Phase::Sorting, sorting);
+
+            // Keeping the new states that are in none of the previous layers
+            let merging = Instant::now();
+            let previous_layers: Vec<PathBuf> = (0..=depth).map(|d| self.layer_path(d)).collect();
+            let mut previous = Merge::new(&previous_layers)?;
+            let mut next_previous = previous.next().transpose()?;
+            let tmp_path = self.dir.join("layer.tmp");
+            let mut writer = BufWriter::new(File::create(&tmp_path)?);
+            let mut layer_size = 0;
+            for state in Merge::new(&runs)? {
+                let state = state?;
+                while next_previous.as_ref().map_or(false, |p| *p < state) {
+                    next_previous = previous.next().transpose()?;
+                }
+                if next_previous.as_ref() != Some(&state) {
+                    write_record(&mut writer, &state)?;
+                    layer_size += 1;
+                }
+            }
+            writer.flush()?;
+            drop(writer);
+            fs::rename(&tmp_path, self.layer_path(depth + 1))?;
+            for run in runs {
+                fs::remove_file(run)?;
+            }
+            stats.add_time(Phase::Merging, merging);
+            stats.duplicates += stats.generated - generated - (stats.pruned - pruned) - layer_size;
+
+            depth += 1;
+            self.write_progress(hash, depth)?;
+            if layer_size == 0 {
+                stats.elapsed = budget.elapsed();
+                return Ok(SolveReport {
+                    outcome: SolveOutcome::Unsolvable,
+                    stats,
+                });
+            }
+            if stats.frontier_by_depth.len() <= depth {
+                stats.frontier_by_depth.resize(depth + 1, 0);
+            }
+            stats.frontier_by_depth[depth] = layer_size;
+        }
+
+        stats.elapsed = budget.elapsed();
+        let best = self.best_partial(level, best)?;
+        Ok(SolveReport {
+            outcome: SolveOutcome::LimitReached {
+                limit: Limit::Depth,
+                best,
+            },
+            stats,
+        })
+    }
+
+    /// Rebuilds the path to the best state found, from its record and its depth
+    fn best_partial(
+        &self,
+        level: &Level,
+        best: Best<(Vec<u8>, usize)>,
+    ) -> io::Result<Option<PartialState>> {
+        best.try_into_partial(|(record, depth)| self.path_to(level, depth, record))
+    }
+
+    fn layer_path(&self, depth: usize) -> PathBuf {
+        self.dir.join(format!("layer_{}.bin", depth))
+    }
+
+    fn progress_path(&self) -> PathBuf {
+        self.dir.join("progress")
+    }
+
+    /// Reads the level hash and the last completed depth
+    fn read_progress(&self) -> io::Result<Option<(u64, usize)>> {
+        let content = match fs::read_to_string(self.progress_path()) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let mut hash = None;
+        let mut depth = None;
+        for line in content.lines() {
+            match line.split_once(' ') {
+                Some(("level", value)) => hash = u64::from_str_radix(value, 16).ok(),
+                Some(("depth", value)) => depth = value.parse().ok(),
+                _ => return Err(invalid("invalid progress file")),
+            }
+        }
+        match (hash, depth) {
+            (Some(hash), Some(depth)) => Ok(Some((hash, depth))),
+            _ => Err(invalid("invalid progress file")),
+        }
+    }
+
+    /// Records the last completed depth, the file is replaced atomically
+    fn write_progress(&self, hash: u64, depth: usize) -> io::Result<()> {
+        let tmp_path = self.dir.join("progress.tmp");
+        fs::write(&tmp_path, format!("level {:016x}\ndepth {}\n", hash, depth))?;
+        fs::rename(tmp_path, self.progress_path())
+    }
+
+    /// Sorts a batch of states and writes it in a new run file
+    fn write_run(&self, index: usize, batch: &mut Vec<Vec<u8>>) -> io::Result<PathBuf> {
+        batch.sort_unstable();
+        batch.dedup();
+        let path = self.dir.join(format!("run_{}.tmp", index));
+        write_records(&path, batch)?;
+        batch.clear();
+        Ok(path)
+    }
+
+    fn decode(&self, level: &Level, record: &[u8]) -> io::Result<Level> {
+        let squares = decode_squares(record, level.grid.squares().len())?;
+        Ok(level_from_key(level, &squares))
+    }
+
+    /// Rebuilds the path to a state of the given layer, by looking in each
+    /// previous layer for a state leading to the current one
+    fn path_to(&self, level: &Level, depth: usize, mut target: Vec<u8>) -> io::Result<Vec<Move>> {
+        let mut moves = vec![];
+        for d in (0..depth).rev() {
+            let mut found = None;
+            'layer: for record in RecordReader::open(&self.layer_path(d))? {
+                let record = record?;
+                let current = self.decode(level, &record)?;
+                for &m in MOVES.iter() {
+                    let mut next = current.clone();
+                    next.apply_move(m);
+                    if encode_squares(next.grid.squares()) == target {
+                        found = Some((record, m));
+                        break 'layer;
+                    }
+                }
+            }
+            let (record, m) =
+                found.ok_or_else(|| invalid("a layer file doesn't match the search"))?;
+            moves.push(m);
+            target = record;
+        }
+        moves.reverse();
+        Ok(moves)
+    }
+}
+
+impl RecordReader {
+    fn open(path: &PathBuf) -> io::Result<Self> {
+        Ok(Self {
+            reader: BufReader::new(File::open(path)?),
+        })
+    }
+
+    /// Reads the next record, None at the end of the file
+    fn read_record(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let mut len = 0;
+        for shift in (0..64).step_by(7) {
+            let mut byte = [0];
+            if self.reader.read(&mut byte)? == 0 {
+                return if shift == 0 {
+                    Ok(None)
+                } else {
+                    Err(invalid("truncated record"))
+                };
+            }
+            len |= usize::from(byte[0] & 0x7f) << shift;
+            if byte[0] & 0x80 == 0 {
+                break;
+            }
+        }
+        let mut record = vec![0; len];
+        self.reader.read_exact(&mut record)?;
+        Ok(Some(record))
+    }
+}
+
+impl Iterator for RecordReader {
+    type Item = io::Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_record().transpose()
+    }
+}
+
+impl Merge {
+    fn new(paths: &[PathBuf]) -> io::Result<Self> {
+        let mut merge = Self {
+            readers: vec![],
+            heap: BinaryHeap::new(),
+            last: None,
+        };
+        for path in paths {
+            let mut reader = RecordReader::open(path)?;
+            if let Some(record) = reader.read_record()? {
+                merge.heap.push(Reverse((record, merge.readers.len())));
+            }
+            merge.readers.push(reader);
+        }
+        Ok(merge)
+    }
+}
+
+impl Iterator for Merge {
+    type Item = io::Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(Reverse((record, source))) = self.heap.pop() {
+            match self.readers[source].read_record() {
+                Ok(Some(next)) => self.heap.push(Reverse((next, source))),
+                Ok(None) => (),
+                Err(e) => return Some(Err(e)),
+            }
+            if self.last.as_ref() != Some(&record) {
+                self.last = Some(record.clone());
+                return Some(Ok(record));
+            }
+        }
+        None
+    }
+}
+
+fn write_record(writer: &mut impl Write, record: &[u8]) -> io::Result<()> {
+    let mut len = vec![];
+    write_varint(&mut len, record.len() as u64);
+    writer.write_all(&len)?;
+    writer.write_all(record)
+}
+
+fn write_records(path: &PathBuf, records: &[Vec<u8>]) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    for record in records {
+        write_record(&mut writer, record)?;
+    }
+    writer.flush()
+}
+
+#[test]
+fn resumes_and_finds_the_shortest_solution() {
+    let level = crate::levels_list::LEVELS_LIST[0].clone();
+    let dir = std::env::temp_dir().join(format!("baba_external_{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+
+    // Stopping after a few layers, then resuming from them
+    assert!(matches!(
+        ExternalBfs::new(dir.clone(), 3, 50)
+            .solve(&level)
+            .unwrap()
+            .outcome,
+        SolveOutcome::LimitReached {
+            limit: Limit::Depth,
+            ..
+        }
+    ));
+    let solution = ExternalBfs::new(dir.clone(), 20, 50)
+        .solve(&level)
+        .unwrap()
+        .outcome
+        .solution()
+        .unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(solution.len(), 8);
+    assert_eq!(
+        level.clone().apply_move_sequence(solution),
+        Some(EndState::Win)
+    );
+}
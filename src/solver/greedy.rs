@@ -0,0 +1,281 @@
+//! A greedy best-first solver, always expanding the state the heuristic deems the
+//! closest to a win: it finds a solution quickly, but not the shortest one
+//! An improving search goes on after its first solution, only following the paths
+//! shorter than the best solution, until the budget runs out or none is left
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::io;
+use std::time::Instant;
+
+use crate::encoding::invalid;
+use crate::interpreter::*;
+use crate::level::Level;
+use crate::solver::checkpoint::*;
+use crate::solver::deadlock::Deadlocks;
+use crate::solver::heuristic::Heuristic;
+use crate::solver::limits::*;
+use crate::solver::reachability::RuleReachability;
+use crate::solver::stats::*;
+use crate::solver::*;
+
+pub struct GreedyBestFirst<H: Heuristic> {
+    heuristic: H,
+    limits: Limits,
+    progress: Option<Progress>,
+    improving: bool,
+}
+
+impl<H: Heuristic> GreedyBestFirst<H> {
+    /// Creates a solver ignoring the solutions longer than `max_depth` moves
+    pub fn new(heuristic: H, max_depth: usize) -> Self {
+        Self {
+            heuristic,
+            limits: Limits::depth(max_depth),
+            progress: None,
+            improving: false,
+        }
+    }
+
+    /// Replaces the limits of the search, including the maximum depth
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Reports the statistics of the search while it runs
+    pub fn with_progress(mut self, progress: Progress) -> Self {
+        self.progress = Some(progress);
+        self
+    }
+
+    /// Keeps searching shorter solutions after the first one. The best solution is
+    /// returned when a limit is hit, it is the shortest one if the search ends by itself
+    pub fn improving(mut self) -> Self {
+        self.improving = true;
+        self
+    }
+
+    pub fn solve(&self, level: &Level) -> SolveReport {
+        self.search(level, None)
+            .expect("No I/O without checkpoints")
+    }
+
+    /// Searches a winning move sequence, saving checkpoints of the search.
+    /// A search stopped at a limit saves a checkpoint it can be resumed from with
+    /// greater limits, but only with the same maximum depth once it cut a path
+    pub fn solve_with_checkpoints(
+        &self,
+        level: &Level,
+        config: CheckpointConfig,
+    ) -> io::Result<SolveReport> {
+        self.search(level, Some(Checkpointer::new(config, level, "greedy")))
+    }
+
+    fn search(
+        &self,
+        level: &Level,
+        mut checkpointer: Option<Checkpointer>,
+    ) -> io::Result<SolveReport> {
+        let mut stats = SearchStats::default();
+        stats.reached(0);
+        if !RuleReachability::analyse(level).can_win() {
+            return Ok(SolveReport {
+                outcome: SolveOutcome::Unsolvable,
+                stats,
+            });
+        }
+        let budget = Budget::new(&self.limits, level);
+        let mut reporter = Reporter::new(&self.progress);
+        let deadlocks = Deadlocks::new(level);
+        let mut best = Best::default();
+        best.offer(level, || 0);
+
+        let mut tree = PathTree::new();
+        // Smallest number of moves reaching each state, a state is opened again
+        // when it is reached with fewer moves
+        let mut seen = HashMap::new();
+        seen.insert(state_key(level), 0);
+        // (estimate, moves, node, state), the nodes are numbered in order of insertion
+        let mut open = BinaryHeap::new();
+        open.push(Reverse((
+            self.heuristic.estimate(level),
+            0,
+            0,
+            state_key(level),
+        )));
+        let mut solution: Option<Vec<Move>> = None;
+        let mut cut = false;
+
+        let saved = match &checkpointer {
+            Some(checkpointer) => checkpointer.load()?,
+            None => None,
+        };
+        if let Some(bytes) = saved {
+            let mut reader = Reader::for_level(&bytes, level);
+            stats = reader.stats()?;
+            tree = reader.path_tree()?;
+            seen.clear();
+            for _ in 0..reader.usize()? {
+                let key = reader.key()?;
+                seen.insert(key, reader.usize()?);
+            }
+            open.clear();
+            for _ in 0..reader.usize()? {
+                let (estimate, g, node) = (reader.usize()?, reader.usize()?, reader.usize()?);
+                open.push(Reverse((estimate, g, node, reader.key()?)));
+            }
+            solution = match reader.varint()? {
+                0 => None,
+                _ => Some(reader.moves()?),
+            };
+            cut = reader.varint()? != 0;
+            // The paths cut by another maximum depth are lost
+            if reader.usize()? != self.limits.max_depth && cut {
+                return Err(invalid(
+                    "the checkpoint was saved with another maximum depth",
+                ));
+            }
+        }
+        let (start, resumed_elapsed) = (Instant::now(), stats.elapsed);
+
+        let stop = loop {
+            stats.elapsed = resumed_elapsed + start.elapsed();
+            stats.memory(budget.memory(seen.len()));
+            reporter.report(&stats);
+            let stop = match open.peek() {
+                None => break None,
+                Some(_) => budget.exceeded(stats.expanded, seen.len()),
+            };
+
+            let write = |writer: &mut Writer| {
+                writer.stats(&stats);
+                writer.path_tree(&tree);
+                writer.varint(seen.len() as u64);
+                for (key, &g) in &seen {
+                    writer.key(key);
+                    writer.varint(g as u64);
+                }
+                writer.varint(open.len() as u64);
+                for Reverse((estimate, g, node, key)) in &open {
+                    writer.varint(*estimate as u64);
+                    writer.varint(*g as u64);
+                    writer.varint(*node as u64);
+                    writer.key(key);
+                }
+                match &solution {
+                    Some(moves) => {
+                        writer.varint(1);
+                        writer.moves(moves);
+                    }
+                    None => writer.varint(0),
+                }
+                writer.varint(cut as u64);
+                writer.varint(self.limits.max_depth as u64);
+            };
+            if let Some(checkpointer) = &mut checkpointer {
+                let saving = Instant::now();
+                if stop.is_some() {
+                    checkpointer.save(write)?;
+                } else {
+                    checkpointer.save_if_due(write)?;
+                }
+                stats.add_time(Phase::Checkpoints, saving);
+            }
+            if stop.is_some() {
+                break stop;
+            }
+
+            let Reverse((_, g, node, key)) = open.pop().expect("The open list isn't empty");
+            if seen.get(&key).map_or(false, |&seen_g| seen_g < g) {
+                continue;
+            }
+
+            stats.expanded += 1;
+            // Only the paths shorter than the best solution can improve it
+            let bound = solution
+                .as_ref()
+                .map_or(self.limits.max_depth, |moves| moves.len() - 1);
+            let current = stats.time(Phase::Expansion, || level_from_key(level, &key));
+            for &m in MOVES.iter() {
+                let mut next = current.clone();
+                let game_state = stats.time(Phase::Expansion, || next.apply_move(m));
+                stats.generated += 1;
+                if g + 1 > bound {
+                    cut |= solution.is_none();
+                    continue;
+                }
+                if game_state == Some(EndState::Win) {
+                    let mut moves = tree.path(node);
+                    moves.push(m);
+                    if !self.improving {
+                        stats.elapsed = resumed_elapsed + start.elapsed();
+                        return Ok(SolveReport {
+                            outcome: SolveOutcome::Solved(moves),
+                            stats,
+                        });
+                    }
+                    solution = Some(moves);
+                    break;
+                }
+
+                let next_key = state_key(&next);
+                let duplicate = stats.time(Phase::Duplicates, || {
+                    seen.get(&next_key).map_or(false, |&seen_g| seen_g <= g + 1)
+                });
+                if duplicate {
+                    stats.duplicates += 1;
+                    continue;
+                }
+                seen.insert(next_key.clone(), g + 1);
+                stats.reached(g + 1);
+                if game_state == Some(EndState::Defeat) {
+                    continue;
+                }
+                if stats.time(Phase::Deadlocks, || deadlocks.is_deadlocked(&next)) {
+                    stats.pruned += 1;
+                    continue;
+                }
+                let child = tree.push(node, m);
+                best.offer(&next, || child);
+                let estimate = stats.time(Phase::Heuristic, || self.heuristic.estimate(&next));
+                open.push(Reverse((estimate, g + 1, child, next_key)));
+            }
+        };
+
+        stats.elapsed = resumed_elapsed + start.elapsed();
+        let outcome = match (solution, stop) {
+            (Some(moves), _) => SolveOutcome::Solved(moves),
+            (None, Some(limit)) => SolveOutcome::LimitReached {
+                limit,
+                best: best.into_partial(|node| tree.path(node)),
+            },
+            (None, None) if cut => SolveOutcome::LimitReached {
+                limit: Limit::Depth,
+                best: best.into_partial(|node| tree.path(node)),
+            },
+            (None, None) => SolveOutcome::Unsolvable,
+        };
+        Ok(SolveReport { outcome, stats })
+    }
+}
+
+#[test]
+fn improves_the_first_solution() {
+    use crate::solver::heuristic::WinDistance;
+
+    let level = crate::levels_list::LEVELS_LIST[0].clone();
+    let first = GreedyBestFirst::new(WinDistance, 100).solve(&level);
+    let first = first.outcome.solution().expect("Level 1 has a solution");
+    assert_eq!(
+        level.clone().apply_move_sequence(first.clone()),
+        Some(EndState::Win)
+    );
+
+    let improved = GreedyBestFirst::new(WinDistance, 30)
+        .improving()
+        .solve(&level);
+    let improved = improved.outcome.solution().expect("Level 1 has a solution");
+    assert_eq!(improved.len(), 8);
+    assert!(improved.len() <= first.len());
+}
@@ -0,0 +1,45 @@
+//! Estimations of the number of moves left to win a level
+
+use crate::level::Level;
+use crate::square::*;
+
+pub trait Heuristic {
+    /// Estimated number of moves left to win from the given level
+    fn estimate(&self, level: &Level) -> usize;
+}
+
+/// Always estimates zero moves, this is admissible and turns an informed
+/// search into a plain uninformed one
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Blind;
+
+impl Heuristic for Blind {
+    fn estimate(&self, _level: &Level) -> usize {
+        0
+    }
+}
+
+/// Manhattan distance between the closest units tagged YOU and WIN, or zero if one
+/// of them is missing. This is only admissible when no rule can be formed or broken,
+/// as a new rule can make a level winnable from afar
+#[derive(Clone, Copy, Debug, Default)]
+pub struct WinDistance;
+
+impl Heuristic for WinDistance {
+    fn estimate(&self, level: &Level) -> usize {
+        let width = level.grid.width();
+        let wins = level.units_with_property(TWIN);
+        level
+            .units_with_property(TYOU)
+            .iter()
+            .flat_map(|&(_, you)| {
+                wins.iter().map(move |&(_, win)| {
+                    let (x1, y1) = (you % width, you / width);
+                    let (x2, y2) = (win % width, win / width);
+                    x1.max(x2) - x1.min(x2) + y1.max(y2) - y1.min(y2)
+                })
+            })
+            .min()
+            .unwrap_or(0)
+    }
+}
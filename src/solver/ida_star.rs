@@ -0,0 +1,351 @@
+//! An iterative-deepening A* solver, it only keeps the current path and a
+//! transposition table of bounded size in memory
+//! The table stores a 64-bit hash of each state, two states with the same hash
+//! are taken for one another, which may in rare cases prune a useful state
+//! The path is walked by playing and undoing the moves in a game session,
+//! so no level is cloned while searching
+//!
+//! The checkpoints are saved between two iterations, or in the middle of one when the
+//! process is interrupted or a limit is hit. A resumed search starts over the iteration
+//! it was saved in
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::time::{Duration, Instant};
+
+use crate::interpreter::*;
+use crate::level::Level;
+use crate::session::GameSession;
+use crate::solver::checkpoint::*;
+use crate::solver::deadlock::Deadlocks;
+use crate::solver::heuristic::Heuristic;
+use crate::solver::limits::*;
+use crate::solver::reachability::RuleReachability;
+use crate::solver::stats::*;
+use crate::solver::*;
+
+/// Default number of states kept in the transposition table, about 32 MB
+pub const DEFAULT_TABLE_SIZE: usize = 1 << 20;
+
+/// Estimated memory used by an entry of the transposition table, in bytes
+const TABLE_ENTRY_MEMORY: usize = 32;
+
+pub struct IdaStar<H: Heuristic> {
+    heuristic: H,
+    table_size: usize,
+    /// The maximum depth is the maximum f-bound
+    limits: Limits,
+    progress: Option<Progress>,
+}
+
+/// The result of a search, with the f-bound of every iteration. The states
+/// reached at each depth are counted again in every iteration
+#[derive(Clone, Debug)]
+pub struct IdaStarResult {
+    pub outcome: SolveOutcome,
+    pub bounds: Vec<usize>,
+    pub stats: SearchStats,
+}
+
+/// The state of the current iteration
+struct Iteration {
+    bound: usize,
+    number: usize,
+    /// State hash -> (iteration, smallest number of moves to reach it in this iteration)
+    table: HashMap<u64, (usize, usize)>,
+    path: HashSet<StateKey>,
+    stats: SearchStats,
+    budget: Budget,
+    best: Best<Vec<Move>>,
+    deadlocks: Deadlocks,
+    reporter: Reporter,
+    start: Instant,
+    resumed_elapsed: Duration,
+    /// Stops the iteration when the process is interrupted
+    interruptible: bool,
+    stop: Option<Stop>,
+}
+
+impl<H: Heuristic> IdaStar<H> {
+    /// Creates a solver giving up when the f-bound goes over `max_bound`
+    pub fn new(heuristic: H, table_size: usize, max_bound: usize) -> Self {
+        Self {
+            heuristic,
+            table_size,
+            limits: Limits::depth(max_bound),
+            progress: None,
+        }
+    }
+
+    /// Replaces the limits of the search, including the maximum f-bound.
+    /// The memory limit counts the states of the path and the entries of the transposition table
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Reports the statistics of the search while it runs
+    pub fn with_progress(mut self, progress: Progress) -> Self {
+        self.progress = Some(progress);
+        self
+    }
+
+    /// Searches a winning move sequence, the solution is the shortest
+    /// one if the heuristic is admissible
+    pub fn solve(&self, level: &Level) -> IdaStarResult {
+        self.search_iterations(level, None)
+            .expect("No I/O without checkpoints")
+    }
+
+    /// Searches a winning move sequence, saving checkpoints of the search.
+    /// A search stopped at a limit saves a checkpoint it can be resumed from
+    /// with greater limits
+    pub fn solve_with_checkpoints(
+        &self,
+        level: &Level,
+        config: CheckpointConfig,
+    ) -> io::Result<IdaStarResult> {
+        self.search_iterations(level, Some(Checkpointer::new(config, level, "ida")))
+    }
+
+    fn search_iterations(
+        &self,
+        level: &Level,
+        mut checkpointer: Option<Checkpointer>,
+    ) -> io::Result<IdaStarResult> {
+        if !RuleReachability::analyse(level).can_win() {
+            return Ok(IdaStarResult {
+                outcome: SolveOutcome::Unsolvable,
+                bounds: vec![],
+                stats: SearchStats::default(),
+            });
+        }
+        let mut session = GameSession::new(level.clone());
+        let mut iteration = Iteration {
+            bound: self.heuristic.estimate(level),
+            number: 0,
+            table: HashMap::new(),
+            path: HashSet::new(),
+            stats: SearchStats::default(),
+            budget: Budget::new(&self.limits, level),
+            best: Best::default(),
+            deadlocks: Deadlocks::new(level),
+            reporter: Reporter::new(&self.progress),
+            start: Instant::now(),
+            resumed_elapsed: Duration::ZERO,
+            interruptible: checkpointer.is_some(),
+            stop: None,
+        };
+        iteration.best.offer(level, Vec::new);
+        iteration.path.insert(state_key(level));
+        let mut bounds = vec![];
+
+        let saved = match &checkpointer {
+            Some(checkpointer) => checkpointer.load()?,
+            None => None,
+        };
+        match saved {
+            Some(bytes) => {
+                let mut reader = Reader::for_level(&bytes, level);
+                iteration.stats = reader.stats()?;
+                iteration.bound = reader.usize()?;
+                iteration.number = reader.usize()?;
+                for _ in 0..reader.usize()? {
+                    bounds.push(reader.usize()?);
+                }
+            }
+            None => iteration.stats.reached(0),
+        }
+        iteration.resumed_elapsed = iteration.stats.elapsed;
+
+        let stop = loop {
+            if iteration.bound > self.limits.max_depth {
+                break Stop::Limit(Limit::Depth);
+            }
+            iteration.update_elapsed();
+            if let Some(checkpointer) = &mut checkpointer {
+                let saving = Instant::now();
+                checkpointer.save_if_due(|writer| write_iteration(writer, &iteration, &bounds))?;
+                iteration.stats.add_time(Phase::Checkpoints, saving);
+            }
+
+            bounds.push(iteration.bound);
+            let result = self.search(&mut session, 0, &mut iteration);
+            if let Some(stop) = iteration.stop.take() {
+                bounds.pop();
+                break stop;
+            }
+            match result {
+                Ok(()) => {
+                    iteration.update_elapsed();
+                    return Ok(IdaStarResult {
+                        outcome: SolveOutcome::Solved(session.moves()),
+                        bounds,
+                        stats: iteration.stats,
+                    });
+                }
+                // Nothing went over the bound, the whole state space was explored
+                Err(usize::MAX) => {
+                    iteration.update_elapsed();
+                    return Ok(IdaStarResult {
+                        outcome: SolveOutcome::Unsolvable,
+                        bounds,
+                        stats: iteration.stats,
+                    });
+                }
+                Err(next_bound) => iteration.bound = next_bound,
+            }
+            iteration.number += 1;
+        };
+
+        // Saving the iteration to start over when resuming
+        if let Some(checkpointer) = &mut checkpointer {
+            iteration.update_elapsed();
+            checkpointer.save(|writer| write_iteration(writer, &iteration, &bounds))?;
+        }
+        match stop {
+            Stop::Interrupted => Err(interruption()),
+            Stop::Limit(limit) => Ok(IdaStarResult {
+                outcome: SolveOutcome::LimitReached {
+                    limit,
+                    best: iteration.best.into_partial(|moves| moves),
+                },
+                bounds,
+                stats: iteration.stats,
+            }),
+        }
+    }
+
+    /// Depth first search under the current bound from the state of the session.
+    /// Returns Ok if a solution was found, the session is then left on the winning state.
+    /// Otherwise returns the smallest f-value over the bound
+    fn search(&self, session: &mut GameSession, g: usize, it: &mut Iteration) -> Result<(), usize> {
+        let f = g + it.stats.time(Phase::Heuristic, || {
+            self.heuristic.estimate(session.level())
+        });
+        if f > it.bound {
+            return Err(f);
+        }
+        if it.interruptible && interrupted() {
+            it.stop = Some(Stop::Interrupted);
+            return Err(usize::MAX);
+        }
+        if let Some(limit) = it.exceeded() {
+            it.stop = Some(Stop::Limit(limit));
+            return Err(usize::MAX);
+        }
+        it.stats.expanded += 1;
+
+        let mut next_bound = usize::MAX;
+        for &m in MOVES.iter() {
+            match it.stats.time(Phase::Expansion, || session.play(m)) {
+                Some(EndState::Win) if g < it.bound => return Ok(()),
+                // A win over the bound is found again in a later iteration
+                Some(EndState::Win) => {
+                    next_bound = next_bound.min(g + 1);
+                    session.undo();
+                    continue;
+                }
+                Some(EndState::Defeat) => {
+                    session.undo();
+                    continue;
+                }
+                None => (),
+            }
+
+            let key = state_key(session.level());
+            it.stats.generated += 1;
+            let duplicates = Instant::now();
+            let duplicate = it.path.contains(&key) || !self.record(it, &key, g + 1);
+            it.stats.add_time(Phase::Duplicates, duplicates);
+            if duplicate {
+                it.stats.duplicates += 1;
+                session.undo();
+                continue;
+            }
+            it.stats.reached(g + 1);
+            let deadlocks = &it.deadlocks;
+            if it.stats.time(Phase::Deadlocks, || {
+                deadlocks.is_deadlocked(session.level())
+            }) {
+                it.stats.pruned += 1;
+                session.undo();
+                continue;
+            }
+
+            it.best.offer(session.level(), || session.moves());
+            it.path.insert(key.clone());
+            match self.search(session, g + 1, it) {
+                Ok(()) => return Ok(()),
+                Err(_) if it.stop.is_some() => return Err(usize::MAX),
+                Err(bound) => next_bound = next_bound.min(bound),
+            }
+            it.path.remove(&key);
+            session.undo();
+        }
+
+        Err(next_bound)
+    }
+
+    /// Records that the state was reached in g moves in this iteration,
+    /// returns false if it was already reached with fewer moves
+    fn record(&self, it: &mut Iteration, key: &StateKey, g: usize) -> bool {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        let hash = hasher.finish();
+        match it.table.get_mut(&hash) {
+            Some(entry) if entry.0 == it.number && entry.1 <= g => false,
+            Some(entry) => {
+                *entry = (it.number, g);
+                true
+            }
+            None => {
+                if it.table.len() < self.table_size {
+                    it.table.insert(hash, (it.number, g));
+                }
+                true
+            }
+        }
+    }
+}
+
+impl Iteration {
+    fn update_elapsed(&mut self) {
+        self.stats.elapsed = self.resumed_elapsed + self.start.elapsed();
+    }
+
+    /// Updates the statistics and reports them, then returns the first limit hit
+    fn exceeded(&mut self) -> Option<Limit> {
+        let memory = self.budget.memory(self.path.len()) + self.table.len() * TABLE_ENTRY_MEMORY;
+        self.update_elapsed();
+        self.stats.memory(memory);
+        self.reporter.report(&self.stats);
+        self.budget.exceeded_bytes(self.stats.expanded, memory)
+    }
+}
+
+/// Writes the iteration to start from when resuming, with the previous bounds
+fn write_iteration(writer: &mut Writer, it: &Iteration, bounds: &[usize]) {
+    writer.stats(&it.stats);
+    writer.varint(it.bound as u64);
+    writer.varint(it.number as u64);
+    writer.varint(bounds.len() as u64);
+    for &bound in bounds {
+        writer.varint(bound as u64);
+    }
+}
+
+#[test]
+fn finds_the_shortest_solution_of_level_1() {
+    let level = crate::levels_list::LEVELS_LIST[0].clone();
+    let result = IdaStar::new(crate::solver::heuristic::Blind, 1000, 20).solve(&level);
+    let solution = result.outcome.solution().expect("Level 1 has a solution");
+    assert_eq!(solution.len(), 8);
+    assert_eq!(result.bounds, (0..=8).collect::<Vec<_>>());
+    assert_eq!(
+        level.clone().apply_move_sequence(solution),
+        Some(EndState::Win)
+    );
+}
@@ -0,0 +1,279 @@
+//! Limits of a search and the outcome of a solver
+//! A search stops at the first limit it hits: number of expanded states, wall-clock
+//! time, estimated memory, number of moves, or a cancellation from another thread
+
+use std::fmt;
+use std::mem;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::interpreter::*;
+use crate::level::Level;
+use crate::solver::heuristic::*;
+
+/// Estimated memory used by a stored state besides its squares,
+/// for the tree node and the hash map entry
+const STATE_OVERHEAD: usize = 64;
+
+/// Cancels a search from another thread, the clones share the same flag
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Asks the searches using this token to stop as soon as possible
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Limits {
+    /// Maximum number of moves of a solution
+    pub max_depth: usize,
+    /// Maximum number of expanded states
+    pub max_nodes: Option<u64>,
+    pub max_time: Option<Duration>,
+    /// Maximum estimated memory used by the stored states, in bytes
+    pub max_memory: Option<usize>,
+    pub cancel: CancelToken,
+}
+
+impl Limits {
+    /// Only limits the number of moves
+    pub fn depth(max_depth: usize) -> Self {
+        Self {
+            max_depth,
+            max_nodes: None,
+            max_time: None,
+            max_memory: None,
+            cancel: CancelToken::new(),
+        }
+    }
+}
+
+/// The limit that stopped a search
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Limit {
+    Depth,
+    Nodes,
+    Time,
+    Memory,
+    /// A beam search dropped states at its widest beam
+    Width,
+    Cancelled,
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Limit::Depth => "depth limit",
+            Limit::Nodes => "node limit",
+            Limit::Time => "time limit",
+            Limit::Memory => "memory limit",
+            Limit::Width => "beam width limit",
+            Limit::Cancelled => "cancellation",
+        };
+        f.pad(name)
+    }
+}
+
+/// The state reached by a search that is the closest to a win,
+/// by the distance between the units tagged YOU and WIN
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PartialState {
+    /// Moves leading from the start to the state
+    pub moves: Vec<Move>,
+    pub win_distance: usize,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SolveOutcome {
+    Solved(Vec<Move>),
+    /// The whole state space was explored without finding a win
+    Unsolvable,
+    /// The search stopped at a limit, with the best state it reached
+    LimitReached {
+        limit: Limit,
+        best: Option<PartialState>,
+    },
+}
+
+impl SolveOutcome {
+    /// Returns the solution if one was found
+    pub fn solution(self) -> Option<Vec<Move>> {
+        match self {
+            SolveOutcome::Solved(moves) => Some(moves),
+            _ => None,
+        }
+    }
+}
+
+/// Checks the limits during a search
+pub struct Budget {
+    limits: Limits,
+    start: Instant,
+    state_memory: usize,
+}
+
+impl Budget {
+    /// Starts the clock of a search of the given level
+    pub fn new(limits: &Limits, level: &Level) -> Self {
+        Self {
+            limits: limits.clone(),
+            start: Instant::now(),
+            state_memory: mem::size_of_val(level.grid.squares()) + STATE_OVERHEAD,
+        }
+    }
+
+    /// Time elapsed since the start of the search
+    pub fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    /// Estimated memory used by the given number of stored states, in bytes
+    pub fn memory(&self, states: usize) -> usize {
+        states.saturating_mul(self.state_memory)
+    }
+
+    /// Returns the first limit hit after expanding `nodes` states and storing `states` states
+    pub fn exceeded(&self, nodes: u64, states: usize) -> Option<Limit> {
+        self.exceeded_bytes(nodes, self.memory(states))
+    }
+
+    /// Returns the first limit hit after expanding `nodes` states and using `memory` bytes
+    pub fn exceeded_bytes(&self, nodes: u64, memory: usize) -> Option<Limit> {
+        if self.limits.cancel.is_cancelled() {
+            Some(Limit::Cancelled)
+        } else if self.limits.max_nodes.map_or(false, |max| nodes >= max) {
+            Some(Limit::Nodes)
+        } else if self.limits.max_memory.map_or(false, |max| memory >= max) {
+            Some(Limit::Memory)
+        } else if self
+            .limits
+            .max_time
+            .map_or(false, |max| self.start.elapsed() >= max)
+        {
+            Some(Limit::Time)
+        } else {
+            None
+        }
+    }
+}
+
+/// Keeps the reached state that is the closest to a win, `T` identifies the
+/// state in the search and is turned into the moves leading to it at the end
+pub struct Best<T> {
+    best: Option<(usize, T)>,
+}
+
+impl<T> Default for Best<T> {
+    fn default() -> Self {
+        Self { best: None }
+    }
+}
+
+impl<T> Best<T> {
+    /// Keeps the state if it is closer to a win than the best one,
+    /// the first state reached is kept on a tie
+    pub fn offer(&mut self, level: &Level, state: impl FnOnce() -> T) {
+        let distance = WinDistance.estimate(level);
+        if self
+            .best
+            .as_ref()
+            .map_or(true, |(best, _)| distance < *best)
+        {
+            self.best = Some((distance, state()));
+        }
+    }
+
+    /// Keeps the best of the two
+    pub fn merge(&mut self, other: Self) {
+        if let Some((distance, state)) = other.best {
+            if self
+                .best
+                .as_ref()
+                .map_or(true, |(best, _)| distance < *best)
+            {
+                self.best = Some((distance, state));
+            }
+        }
+    }
+
+    /// Returns the best state with the moves leading to it
+    pub fn into_partial(self, moves: impl FnOnce(T) -> Vec<Move>) -> Option<PartialState> {
+        self.best.map(|(win_distance, state)| PartialState {
+            moves: moves(state),
+            win_distance,
+        })
+    }
+
+    /// Returns the best state with the moves leading to it, when finding them can fail
+    pub fn try_into_partial<E>(
+        self,
+        moves: impl FnOnce(T) -> Result<Vec<Move>, E>,
+    ) -> Result<Option<PartialState>, E> {
+        self.best
+            .map(|(win_distance, state)| {
+                Ok(PartialState {
+                    moves: moves(state)?,
+                    win_distance,
+                })
+            })
+            .transpose()
+    }
+}
+
+#[test]
+fn reports_why_the_search_stopped() {
+    use crate::level::*;
+    use crate::solver::bfs::Bfs;
+    use crate::square::*;
+
+    let level = crate::levels_list::LEVELS_LIST[0].clone();
+    let cancel = CancelToken::new();
+    cancel.cancel();
+    let limits = Limits {
+        cancel,
+        ..Limits::depth(20)
+    };
+    assert!(matches!(
+        Bfs::new(20).with_limits(limits).solve(&level).outcome,
+        SolveOutcome::LimitReached {
+            limit: Limit::Cancelled,
+            ..
+        }
+    ));
+
+    let limits = Limits {
+        max_nodes: Some(10),
+        ..Limits::depth(20)
+    };
+    match Bfs::new(20).with_limits(limits).solve(&level).outcome {
+        SolveOutcome::LimitReached {
+            limit: Limit::Nodes,
+            best: Some(best),
+        } => {
+            let mut reached = level.clone();
+            reached.apply_move_sequence(best.moves);
+            assert_eq!(WinDistance.estimate(&reached), best.win_distance);
+        }
+        outcome => panic!("Unexpected outcome {:?}", outcome),
+    }
+
+    // Without a WIN text, no rule can make the level winnable
+    let mut level = Level::new(4, 2);
+    level.add_rule(&[TBABA, TIS, TYOU], (0, 0), HORIZONTAL);
+    level.add_square(Entity::BABA.into(), (0, 1));
+    assert_eq!(Bfs::new(20).solve(&level).outcome, SolveOutcome::Unsolvable);
+}
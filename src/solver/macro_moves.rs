@@ -0,0 +1,554 @@
+//! A breadth first solver over macro moves: walking over free squares to a unit
+//! that can be pushed or won on, then moving into it
+//! Walking over squares with no unit tagged STOP, PUSH, WIN or DEFEAT changes nothing
+//! but the position of YOU, so every solution is a sequence of such macro moves.
+//! A search is then as deep as the number of interactions instead of the number of moves,
+//! the solutions found have the fewest interactions but not always the fewest moves.
+//! A state reached again with fewer plain moves is explored again from the shorter
+//! path, so that the maximum number of moves only cuts the paths that can't fit in it.
+//! The normalized keys are the exception, see `with_normalized_keys`
+//!
+//! The macro moves only apply when a single unit is tagged YOU and no transformation
+//! is pending, the single moves are tried otherwise
+//!
+//! The checkpoints are saved between the expansions of two states, as in the
+//! breadth first solver
+
+use std::collections::VecDeque;
+use std::io;
+use std::time::Instant;
+
+use crate::encoding::invalid;
+use crate::interpreter::*;
+use crate::level::Level;
+use crate::solver::checkpoint::*;
+use crate::solver::deadlock::Deadlocks;
+use crate::solver::limits::*;
+use crate::solver::reachability::RuleReachability;
+use crate::solver::stats::*;
+use crate::solver::*;
+
+/// Walking along a shortest path then playing a move into an interactive unit
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MacroMove {
+    pub walk: Vec<Move>,
+    pub push: Move,
+}
+
+impl MacroMove {
+    /// The plain moves of the macro move
+    pub fn moves(&self) -> impl Iterator<Item = Move> + '_ {
+        self.walk.iter().cloned().chain(Some(self.push))
+    }
+}
+
+impl Edge for MacroMove {
+    fn write(&self, writer: &mut Writer) {
+        writer.moves(&self.walk);
+        writer.move_(self.push);
+    }
+
+    fn read(reader: &mut Reader) -> io::Result<Self> {
+        Ok(Self {
+            walk: reader.moves()?,
+            push: reader.move_()?,
+        })
+    }
+}
+
+/// Expands macro moves back into plain moves
+pub fn expand(macros: &[MacroMove]) -> Vec<Move> {
+    macros.iter().flat_map(MacroMove::moves).collect()
+}
+
+/// Shortest paths of the single unit tagged YOU over the free squares
+struct Walks {
+    you: (LayeredSquare, usize),
+    /// Square -> move reaching it from the previous square of its path, None if unreached
+    parents: Vec<Option<Option<(usize, Move)>>>,
+}
+
+impl Walks {
+    /// Returns None if walking could change anything else than the position of YOU
+    fn new(level: &Level) -> Option<Self> {
+        let yous = level.units_with_property(TYOU);
+        if yous.len() != 1 || transformation_pending(level) {
+            return None;
+        }
+        let grid = &level.grid;
+        let mut walks = Self {
+            you: yous[0],
+            parents: vec![None; grid.squares().len()],
+        };
+        walks.parents[walks.you.1] = Some(None);
+        let mut queue = VecDeque::new();
+        queue.push_back(walks.you.1);
+        while let Some(pos) = queue.pop_front() {
+            for &m in MOVES.iter() {
+                if let Some(next) = grid.apply_move(pos, m) {
+                    if walks.parents[next].is_none() && is_free(level, next) {
+                        walks.parents[next] = Some(Some((pos, m)));
+                        queue.push_back(next);
+                    }
+                }
+            }
+        }
+        Some(walks)
+    }
+
+    /// The squares YOU can walk to, its own one included
+    fn reached(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..self.parents.len()).filter(move |&pos| self.parents[pos].is_some())
+    }
+
+    /// Returns the moves of the shortest path to the square
+    fn to(&self, mut pos: usize) -> Vec<Move> {
+        let mut walk = vec![];
+        while let Some(Some((parent, m))) = self.parents[pos] {
+            walk.push(m);
+            pos = parent;
+        }
+        walk.reverse();
+        walk
+    }
+}
+
+/// Returns if walking on the square has no effect
+fn is_free(level: &Level, pos: usize) -> bool {
+    [TSTOP, TPUSH, TWIN, TDEFEAT]
+        .iter()
+        .all(|&property| !level.rules.square_has_property(level.grid[pos], property))
+}
+
+/// Returns the macro moves from the state, or the single moves if walking
+/// could change anything else than the position of YOU
+pub fn macro_moves(level: &Level) -> Vec<MacroMove> {
+    let walks = match Walks::new(level) {
+        Some(walks) => walks,
+        None => {
+            return MOVES
+                .iter()
+                .map(|&push| MacroMove { walk: vec![], push })
+                .collect()
+        }
+    };
+    let has = |pos: usize, property| level.rules.square_has_property(level.grid[pos], property);
+    let mut macros = vec![];
+    for pos in walks.reached() {
+        for &m in MOVES.iter() {
+            match level.grid.apply_move(pos, m) {
+                Some(next) if (has(next, TPUSH) || has(next, TWIN)) && !has(next, TDEFEAT) => {
+                    macros.push(MacroMove {
+                        walk: walks.to(pos),
+                        push: m,
+                    })
+                }
+                _ => (),
+            }
+        }
+    }
+    macros
+}
+
+/// Returns the moves of a shortest walk of YOU to the square over free squares,
+/// None if YOU can't walk there or walking could change anything else
+pub fn walk_to(level: &Level, pos: usize) -> Option<Vec<Move>> {
+    Walks::new(level)
+        .filter(|walks| walks.parents[pos].is_some())
+        .map(|walks| walks.to(pos))
+}
+
+/// Returns the key of the state with YOU moved to the first square it can walk to,
+/// the states only differing by a walk have the same key. YOU stays in place when
+/// it stands on another unit, so a winning state keeps its own key
+pub fn normalized_key(level: &Level) -> StateKey {
+    let mut key = state_key(level);
+    if let Some(walks) = Walks::new(level).filter(|walks| is_free(level, walks.you.1)) {
+        let (layer, pos) = walks.you;
+        let canonical = walks.reached().next().expect("YOU reaches its own square");
+        key[pos].remove_layer(layer);
+        key[canonical].add_layer(layer);
+    }
+    key
+}
+
+/// Returns if some units will be transformed at the end of the next turn
+fn transformation_pending(level: &Level) -> bool {
+    ENTITIES
+        .iter()
+        .filter(|&&e| e != Entity::EMPTY && e != Entity::TEXT)
+        .any(|&entity| {
+            !level.grid[LayeredSquare::from(entity)].is_empty()
+                && !level.rules[entity][usize::from(Text::from(entity))]
+                && ENTITIES.iter().any(|&target| {
+                    target != entity
+                        && target != Entity::EMPTY
+                        && target != Entity::TEXT
+                        && level.rules[entity][usize::from(Text::from(target))]
+                })
+        })
+}
+
+/// Plays a macro move, walking by moving the unit tagged YOU directly
+pub fn play(level: &mut Level, macro_move: &MacroMove) -> GameState {
+    if !macro_move.walk.is_empty() {
+        let (layer, mut pos) = level.units_with_property(TYOU)[0];
+        let start = pos;
+        for &m in &macro_move.walk {
+            pos = level
+                .grid
+                .apply_move(pos, m)
+                .expect("The walk stays inside the grid");
+        }
+        level.remove_layer_internal(layer, start);
+        level.add_layer_internal(layer, pos);
+    }
+    level.apply_move(macro_move.push)
+}
+
+pub struct MacroBfs {
+    limits: Limits,
+    progress: Option<Progress>,
+    normalized: bool,
+}
+
+impl MacroBfs {
+    /// Creates a solver giving up on the solutions longer than `max_depth` moves
+    pub fn new(max_depth: usize) -> Self {
+        Self {
+            limits: Limits::depth(max_depth),
+            progress: None,
+            normalized: false,
+        }
+    }
+
+    /// Replaces the limits of the search, the maximum depth counts the plain moves
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Reports the statistics of the search while it runs, the depths count macro moves
+    pub fn with_progress(mut self, progress: Progress) -> Self {
+        self.progress = Some(progress);
+        self
+    }
+
+    /// Identifies the states by their `normalized_key`, a state is then explored once
+    /// whatever square YOU walked to, through the first path reaching it. The walks of
+    /// its successors start from the square of that path, so a path with fewer plain
+    /// moves doesn't replace it: the maximum depth can cut a solution fitting in it
+    pub fn with_normalized_keys(mut self) -> Self {
+        self.normalized = true;
+        self
+    }
+
+    /// Returns the key identifying the state in the explored ones
+    fn key(&self, level: &Level) -> StateKey {
+        if self.normalized {
+            normalized_key(level)
+        } else {
+            state_key(level)
+        }
+    }
+
+    /// Searches a winning sequence with the fewest macro moves, returned as plain moves
+    pub fn solve(&self, level: &Level) -> SolveReport {
+        self.search(level, None)
+            .expect("No I/O without checkpoints")
+    }
+
+    /// Searches a winning sequence with the fewest macro moves, saving checkpoints of
+    /// the search. A search stopped at a limit saves a checkpoint it can be resumed
+    /// from with greater limits, but only with the same maximum depth once it cut a path
+    pub fn solve_with_checkpoints(
+        &self,
+        level: &Level,
+        config: CheckpointConfig,
+    ) -> io::Result<SolveReport> {
+        // The trees of the two kinds of keys can't be mixed
+        let solver = if self.normalized {
+            "normalized macro"
+        } else {
+            "macro"
+        };
+        self.search(level, Some(Checkpointer::new(config, level, solver)))
+    }
+
+    fn search(
+        &self,
+        level: &Level,
+        mut checkpointer: Option<Checkpointer>,
+    ) -> io::Result<SolveReport> {
+        let mut stats = SearchStats::default();
+        stats.reached(0);
+        if !RuleReachability::analyse(level).can_win() {
+            return Ok(SolveReport {
+                outcome: SolveOutcome::Unsolvable,
+                stats,
+            });
+        }
+        let budget = Budget::new(&self.limits, level);
+        let mut reporter = Reporter::new(&self.progress);
+        let deadlocks = Deadlocks::new(level);
+        let mut best = Best::default();
+        best.offer(level, || 0);
+        let mut tree: SearchTree<MacroMove> = SearchTree::new(self.key(level));
+        // (node, depth in macro moves, depth in plain moves, concrete state)
+        let mut frontier = VecDeque::new();
+        frontier.push_back((0, 0, 0, state_key(level)));
+        // Node -> fewest plain moves reaching it
+        let mut plain_moves = vec![0];
+        let mut cut = false;
+
+        let saved = match &checkpointer {
+            Some(checkpointer) => checkpointer.load()?,
+            None => None,
+        };
+        if let Some(bytes) = saved {
+            let mut reader = Reader::for_level(&bytes, level);
+            stats = reader.stats()?;
+            tree = reader.tree()?;
+            plain_moves = (0..reader.usize()?)
+                .map(|_| reader.usize())
+                .collect::<io::Result<_>>()?;
+            frontier.clear();
+            for _ in 0..reader.usize()? {
+                let (node, depth, moves) = (reader.usize()?, reader.usize()?, reader.usize()?);
+                frontier.push_back((node, depth, moves, reader.key()?));
+            }
+            cut = reader.varint()? != 0;
+            // The paths cut by another maximum depth are lost
+            if reader.usize()? != self.limits.max_depth && cut {
+                return Err(invalid(
+                    "the checkpoint was saved with another maximum depth",
+                ));
+            }
+        }
+        let (start, resumed_elapsed) = (Instant::now(), stats.elapsed);
+
+        let limit = loop {
+            stats.elapsed = resumed_elapsed + start.elapsed();
+            stats.memory(budget.memory(tree.len()));
+            reporter.report(&stats);
+            let stop = match frontier.front() {
+                // Every state was explored but some were cut by the maximum number of moves
+                None if cut => Some(Limit::Depth),
+                None => {
+                    return Ok(SolveReport {
+                        outcome: SolveOutcome::Unsolvable,
+                        stats,
+                    })
+                }
+                Some(_) => budget.exceeded(stats.expanded, tree.len()),
+            };
+
+            let write = |writer: &mut Writer| {
+                writer.stats(&stats);
+                writer.tree(&tree);
+                writer.varint(plain_moves.len() as u64);
+                for &moves in &plain_moves {
+                    writer.varint(moves as u64);
+                }
+                writer.varint(frontier.len() as u64);
+                for (node, depth, moves, key) in &frontier {
+                    writer.varint(*node as u64);
+                    writer.varint(*depth as u64);
+                    writer.varint(*moves as u64);
+                    writer.key(key);
+                }
+                writer.varint(cut as u64);
+                writer.varint(self.limits.max_depth as u64);
+            };
+            if let Some(checkpointer) = &mut checkpointer {
+                let saving = Instant::now();
+                if stop.is_some() {
+                    checkpointer.save(write)?;
+                } else {
+                    checkpointer.save_if_due(write)?;
+                }
+                stats.add_time(Phase::Checkpoints, saving);
+            }
+            if let Some(limit) = stop {
+                break limit;
+            }
+
+            let (node, depth, moves, key) = frontier.pop_front().expect("The frontier isn't empty");
+            // The state was reached again with fewer moves and queued again
+            if moves > plain_moves[node] {
+                continue;
+            }
+
+            stats.expanded += 1;
+            let current = stats.time(Phase::Expansion, || level_from_key(level, &key));
+            for macro_move in stats.time(Phase::Expansion, || macro_moves(&current)) {
+                let next_moves = moves + macro_move.walk.len() + 1;
+                if next_moves > self.limits.max_depth {
+                    cut = true;
+                    continue;
+                }
+                let mut next = current.clone();
+                let game_state = stats.time(Phase::Expansion, || play(&mut next, &macro_move));
+                stats.generated += 1;
+                let next_key = self.key(&next);
+                let inserted = stats.time(Phase::Duplicates, || {
+                    tree.insert(next_key.clone(), Some((node, macro_move.clone())))
+                });
+                let child = match inserted {
+                    Some(child) => {
+                        stats.reached(depth + 1);
+                        plain_moves.push(next_moves);
+                        child
+                    }
+                    None => {
+                        stats.duplicates += 1;
+                        match tree.get(&next_key) {
+                            Some(known) if !self.normalized && next_moves < plain_moves[known] => {
+                                tree.relink(known, (node, macro_move));
+                                plain_moves[known] = next_moves;
+                                known
+                            }
+                            _ => continue,
+                        }
+                    }
+                };
+                match game_state {
+                    Some(EndState::Win) => {
+                        let moves = expand(&tree.path(child));
+                        // The plain moves are played again in case a walk doesn't replay
+                        if level.clone().apply_move_sequence(moves.clone()) != Some(EndState::Win) {
+                            continue;
+                        }
+                        stats.elapsed = resumed_elapsed + start.elapsed();
+                        return Ok(SolveReport {
+                            outcome: SolveOutcome::Solved(moves),
+                            stats,
+                        });
+                    }
+                    Some(EndState::Defeat) => (),
+                    None if stats.time(Phase::Deadlocks, || deadlocks.is_deadlocked(&next)) => {
+                        stats.pruned += 1;
+                    }
+                    None => {
+                        best.offer(&next, || child);
+                        frontier.push_back((child, depth + 1, next_moves, state_key(&next)));
+                    }
+                }
+            }
+        };
+
+        stats.elapsed = resumed_elapsed + start.elapsed();
+        Ok(SolveReport {
+            outcome: SolveOutcome::LimitReached {
+                limit,
+                best: best.into_partial(|node| expand(&tree.path(node))),
+            },
+            stats,
+        })
+    }
+}
+
+#[test]
+fn walks_to_the_rocks_of_level_1() {
+    let level = crate::levels_list::LEVELS_LIST[0].clone();
+    // Baba can walk to the three rocks and to the texts
+    let first = macro_moves(&level);
+    assert!(first.contains(&MacroMove {
+        walk: vec![RIGHT; 3],
+        push: RIGHT,
+    }));
+
+    let report = MacroBfs::new(100).solve(&level);
+    let solution = report.outcome.solution().expect("Level 1 has a solution");
+    assert_eq!(
+        level.clone().apply_move_sequence(solution),
+        Some(EndState::Win)
+    );
+    assert!(report.stats.frontier_by_depth.len() < 9);
+}
+
+#[test]
+fn walks_share_a_normalized_key() {
+    let level = crate::levels_list::LEVELS_LIST[0].clone();
+    let (mut left, mut up) = (level.clone(), level.clone());
+    left.apply_move(LEFT);
+    up.apply_move(UP);
+    assert_ne!(state_key(&left), state_key(&up));
+    assert_eq!(normalized_key(&left), normalized_key(&up));
+
+    let plain = MacroBfs::new(100).solve(&level);
+    let normalized = MacroBfs::new(100).with_normalized_keys().solve(&level);
+    let solution = normalized
+        .outcome
+        .solution()
+        .expect("Level 1 has a solution");
+    assert_eq!(
+        level.clone().apply_move_sequence(solution),
+        Some(EndState::Win)
+    );
+    assert!(normalized.stats.generated <= plain.stats.generated);
+}
+
+#[test]
+fn fits_the_solutions_in_the_maximum_depth() {
+    use crate::level::*;
+
+    // Forming FLAG IS WIN takes pushes that can be played in orders of various lengths
+    let mut level = Level::new(8, 8);
+    level.add_rule(&[TBABA, TIS, TYOU], (0, 0), HORIZONTAL);
+    level.add_rule(&[TROCK, TIS, TPUSH], (4, 0), HORIZONTAL);
+    level.add_rule(&[TWALL, TIS, TSTOP], (0, 1), HORIZONTAL);
+    level.add_square_line(Entity::WALL.into(), (0, 2), 8, HORIZONTAL);
+    level.add_square(Entity::BABA.into(), (1, 6));
+    level.add_square(Entity::FLAG.into(), (0, 3));
+    level.add_square(TFLAG.into(), (2, 7));
+    level.add_square(TIS.into(), (2, 5));
+    level.add_square(TWIN.into(), (5, 5));
+    level.add_square(Entity::WALL.into(), (0, 5));
+    level.add_square(Entity::WALL.into(), (4, 6));
+    // The shortest solutions take 24 moves
+    let solution = MacroBfs::new(24)
+        .solve(&level)
+        .outcome
+        .solution()
+        .expect("A solution fits in 24 moves");
+    assert_eq!(solution.len(), 24);
+    assert_eq!(
+        level.clone().apply_move_sequence(solution),
+        Some(EndState::Win)
+    );
+}
+
+#[test]
+fn normalized_solutions_replay() {
+    use crate::level::*;
+
+    // Forming FLAG IS WIN brings Baba back to regions it left from other squares
+    let mut level = Level::new(8, 8);
+    level.add_rule(&[TBABA, TIS, TYOU], (0, 0), HORIZONTAL);
+    level.add_rule(&[TROCK, TIS, TPUSH], (4, 0), HORIZONTAL);
+    level.add_rule(&[TWALL, TIS, TSTOP], (0, 1), HORIZONTAL);
+    level.add_square_line(Entity::WALL.into(), (0, 2), 8, HORIZONTAL);
+    level.add_square(Entity::BABA.into(), (7, 5));
+    level.add_square(Entity::FLAG.into(), (1, 3));
+    level.add_square(TFLAG.into(), (5, 7));
+    level.add_square(TIS.into(), (3, 5));
+    level.add_square(TWIN.into(), (3, 6));
+    level.add_square(Entity::WALL.into(), (3, 4));
+    level.add_square(Entity::WALL.into(), (1, 6));
+    let solve = |max_depth| {
+        MacroBfs::new(max_depth)
+            .with_normalized_keys()
+            .solve(&level)
+            .outcome
+            .solution()
+    };
+    // The shorter paths found later can't replace the first ones, 40 moves don't suffice
+    assert_eq!(solve(40), None);
+    let solution = solve(60).expect("The level has a solution");
+    assert!(solution.len() <= 60);
+    assert_eq!(
+        level.clone().apply_move_sequence(solution),
+        Some(EndState::Win)
+    );
+}
@@ -0,0 +1,513 @@
+//! A Monte Carlo tree search solver, for levels where the heuristics are misleading
+//! Each iteration walks down the tree with the UCT formula, expands a new state and
+//! plays a rollout from it: random moves, some of them picked by the heuristic.
+//! A rollout that does not win is rewarded by how close to a win it ends
+//!
+//! The nodes are shared by every path reaching their state, the walk down the tree
+//! avoids the states already on its path. The first solution found is returned,
+//! it is rarely the shortest one. The search never proves a level unsolvable, it stops
+//! at its limits: without a node or time limit, after `DEFAULT_ITERATIONS` iterations
+//!
+//! The checkpoints are saved between two iterations, with the state of the
+//! random generator so that a resumed search makes the same moves
+
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::time::Instant;
+
+use crate::interpreter::*;
+use crate::level::Level;
+use crate::solver::checkpoint::*;
+use crate::solver::deadlock::Deadlocks;
+use crate::solver::heuristic::Heuristic;
+use crate::solver::limits::*;
+use crate::solver::reachability::RuleReachability;
+use crate::solver::stats::*;
+use crate::solver::*;
+
+/// Iterations run when neither a node nor a time limit is given
+pub const DEFAULT_ITERATIONS: u64 = 100_000;
+/// Default number of moves of a rollout
+pub const DEFAULT_ROLLOUT_DEPTH: usize = 50;
+/// Weight of the exploration in the UCT formula, for rewards between 0 and 1
+const EXPLORATION: f64 = std::f64::consts::FRAC_1_SQRT_2;
+/// Share of the rollout moves picked by the heuristic rather than at random
+const GUIDED_MOVES: f64 = 0.5;
+
+/// A xorshift pseudo-random generator, seeded for reproducible searches
+#[derive(Clone, Debug)]
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        // Mixing the seed as the generator can't start from zero
+        let mut z = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        Self((z ^ (z >> 31)).max(1))
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// Returns a number in `0..n`
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+
+    /// Returns a number in `[0, 1)`
+    pub fn unit(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+pub struct MonteCarlo<H: Heuristic> {
+    heuristic: H,
+    seed: u64,
+    rollout_depth: usize,
+    limits: Limits,
+    progress: Option<Progress>,
+}
+
+/// The state reached by a move from a node
+#[derive(Clone, Copy, Debug)]
+enum Child {
+    Node(usize),
+    /// Lost or stuck in a deadlock
+    Lost,
+}
+
+struct Node {
+    key: StateKey,
+    visits: u32,
+    reward: f64,
+    /// The child of each move, None until the node is expanded
+    children: Option<Vec<(Move, Child)>>,
+}
+
+/// Why an iteration ended
+enum Iteration {
+    Won(Vec<Move>),
+    Reward(f64),
+}
+
+/// The state of a search
+struct Search<'a> {
+    level: &'a Level,
+    rng: Rng,
+    nodes: Vec<Node>,
+    index: HashMap<StateKey, usize>,
+    deadlocks: Deadlocks,
+    stats: SearchStats,
+    best: Best<Vec<Move>>,
+}
+
+impl<H: Heuristic> MonteCarlo<H> {
+    /// Creates a solver ignoring the solutions longer than `max_depth` moves
+    pub fn new(heuristic: H, seed: u64, max_depth: usize) -> Self {
+        Self {
+            heuristic,
+            seed,
+            rollout_depth: DEFAULT_ROLLOUT_DEPTH,
+            limits: Limits::depth(max_depth),
+            progress: None,
+        }
+    }
+
+    /// Replaces the limits of the search, including the maximum depth.
+    /// The node limit counts the iterations, each one expands at most one state
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Reports the statistics of the search while it runs
+    pub fn with_progress(mut self, progress: Progress) -> Self {
+        self.progress = Some(progress);
+        self
+    }
+
+    /// Sets the number of moves played by a rollout
+    pub fn with_rollout_depth(mut self, rollout_depth: usize) -> Self {
+        self.rollout_depth = rollout_depth;
+        self
+    }
+
+    pub fn solve(&self, level: &Level) -> SolveReport {
+        self.search(level, None)
+            .expect("No I/O without checkpoints")
+    }
+
+    /// Searches a winning move sequence, saving checkpoints of the search.
+    /// A search stopped at a limit saves a checkpoint it can be resumed from
+    /// with greater limits
+    pub fn solve_with_checkpoints(
+        &self,
+        level: &Level,
+        config: CheckpointConfig,
+    ) -> io::Result<SolveReport> {
+        self.search(level, Some(Checkpointer::new(config, level, "mcts")))
+    }
+
+    fn search(
+        &self,
+        level: &Level,
+        mut checkpointer: Option<Checkpointer>,
+    ) -> io::Result<SolveReport> {
+        let mut search = Search {
+            level,
+            rng: Rng::new(self.seed),
+            nodes: vec![],
+            index: HashMap::new(),
+            deadlocks: Deadlocks::new(level),
+            stats: SearchStats::default(),
+            best: Best::default(),
+        };
+        search.stats.reached(0);
+        search.best.offer(level, Vec::new);
+        if !RuleReachability::analyse(level).can_win() {
+            return Ok(SolveReport {
+                outcome: SolveOutcome::Unsolvable,
+                stats: search.stats,
+            });
+        }
+        let mut iterations = 0;
+        let saved = match &checkpointer {
+            Some(checkpointer) => checkpointer.load()?,
+            None => None,
+        };
+        match saved {
+            Some(bytes) => {
+                let mut reader = Reader::for_level(&bytes, level);
+                search.stats = reader.stats()?;
+                iterations = reader.varint()?;
+                search.read(&mut reader)?;
+            }
+            None => {
+                search.add_node(state_key(level));
+            }
+        }
+        let (start, resumed_elapsed) = (Instant::now(), search.stats.elapsed);
+
+        let mut limits = self.limits.clone();
+        if limits.max_nodes.is_none() && limits.max_time.is_none() {
+            limits.max_nodes = Some(DEFAULT_ITERATIONS);
+        }
+        let budget = Budget::new(&limits, level);
+        let mut reporter = Reporter::new(&self.progress);
+        let limit = loop {
+            search.stats.elapsed = resumed_elapsed + start.elapsed();
+            search.stats.memory(budget.memory(search.nodes.len()));
+            reporter.report(&search.stats);
+            let stop = budget.exceeded(iterations, search.nodes.len());
+
+            let write = |writer: &mut Writer| {
+                writer.stats(&search.stats);
+                writer.varint(iterations);
+                search.write(writer);
+            };
+            if let Some(checkpointer) = &mut checkpointer {
+                let saving = Instant::now();
+                if stop.is_some() {
+                    checkpointer.save(write)?;
+                } else {
+                    checkpointer.save_if_due(write)?;
+                }
+                search.stats.add_time(Phase::Checkpoints, saving);
+            }
+            if let Some(limit) = stop {
+                break limit;
+            }
+
+            iterations += 1;
+            if let Some(moves) = self.iterate(&mut search) {
+                search.stats.elapsed = resumed_elapsed + start.elapsed();
+                return Ok(SolveReport {
+                    outcome: SolveOutcome::Solved(moves),
+                    stats: search.stats,
+                });
+            }
+        };
+
+        search.stats.elapsed = resumed_elapsed + start.elapsed();
+        Ok(SolveReport {
+            outcome: SolveOutcome::LimitReached {
+                limit,
+                best: search.best.into_partial(|moves| moves),
+            },
+            stats: search.stats,
+        })
+    }
+
+    /// Walks down the tree, expands a state and plays a rollout from it,
+    /// returns the moves of a win if one was found
+    fn iterate(&self, search: &mut Search) -> Option<Vec<Move>> {
+        let mut path = vec![0];
+        let mut on_path = HashSet::new();
+        on_path.insert(0);
+        let mut moves = vec![];
+
+        let result = loop {
+            let id = *path.last().expect("The path starts at the root");
+            // A child of a node at the maximum depth would make a longer solution
+            if moves.len() >= self.limits.max_depth {
+                break Iteration::Reward(0.0);
+            }
+            if search.nodes[id].children.is_none() {
+                break self.expand(search, id, &moves);
+            }
+            let parent_visits = f64::from(search.nodes[id].visits.max(1));
+            let children = search.nodes[id]
+                .children
+                .as_ref()
+                .expect("The node is expanded");
+            let uct = |child: usize| {
+                let node = &search.nodes[child];
+                if node.visits == 0 {
+                    f64::INFINITY
+                } else {
+                    let visits = f64::from(node.visits);
+                    node.reward / visits + EXPLORATION * (parent_visits.ln() / visits).sqrt()
+                }
+            };
+            let selected = children
+                .iter()
+                .filter_map(|&(m, child)| match child {
+                    Child::Node(child) if !on_path.contains(&child) => Some((m, child)),
+                    _ => None,
+                })
+                .max_by(|a, b| uct(a.1).total_cmp(&uct(b.1)));
+            match selected {
+                Some((m, child)) => {
+                    moves.push(m);
+                    path.push(child);
+                    on_path.insert(child);
+                }
+                // Every move loses or goes back on the path
+                None => break Iteration::Reward(0.0),
+            }
+        };
+
+        match result {
+            Iteration::Won(moves) => Some(moves),
+            Iteration::Reward(reward) => {
+                for id in path {
+                    search.nodes[id].visits += 1;
+                    search.nodes[id].reward += reward;
+                }
+                None
+            }
+        }
+    }
+
+    /// Creates the children of the node then plays a rollout from it
+    fn expand(&self, search: &mut Search, id: usize, moves: &[Move]) -> Iteration {
+        let (level, key) = (search.level, &search.nodes[id].key);
+        search.stats.expanded += 1;
+        let current = search
+            .stats
+            .time(Phase::Expansion, || level_from_key(level, key));
+        let mut children = vec![];
+        for &m in MOVES.iter() {
+            let mut next = current.clone();
+            let game_state = search.stats.time(Phase::Expansion, || next.apply_move(m));
+            search.stats.generated += 1;
+            let child = match game_state {
+                Some(EndState::Win) => return Iteration::Won(with_move(moves, m)),
+                Some(EndState::Defeat) => Child::Lost,
+                None => {
+                    let key = state_key(&next);
+                    match search.index.get(&key) {
+                        Some(&child) => {
+                            search.stats.duplicates += 1;
+                            Child::Node(child)
+                        }
+                        None => {
+                            search.stats.reached(moves.len() + 1);
+                            let deadlocks = &search.deadlocks;
+                            let deadlocked = search
+                                .stats
+                                .time(Phase::Deadlocks, || deadlocks.is_deadlocked(&next));
+                            if deadlocked {
+                                search.stats.pruned += 1;
+                                Child::Lost
+                            } else {
+                                search.best.offer(&next, || with_move(moves, m));
+                                Child::Node(search.add_node(key))
+                            }
+                        }
+                    }
+                }
+            };
+            children.push((m, child));
+        }
+        search.nodes[id].children = Some(children);
+
+        let depth = self.rollout_depth.min(self.limits.max_depth - moves.len());
+        self.rollout(search, current, moves, depth)
+    }
+
+    /// Plays random and heuristic moves from the state
+    fn rollout(
+        &self,
+        search: &mut Search,
+        mut level: Level,
+        moves: &[Move],
+        depth: usize,
+    ) -> Iteration {
+        let start = std::time::Instant::now();
+        let mut played = moves.to_vec();
+        for _ in 0..depth {
+            let m = if search.rng.unit() < GUIDED_MOVES {
+                self.guided_move(search, &level)
+            } else {
+                MOVES[search.rng.below(MOVES.len())]
+            };
+            played.push(m);
+            match level.apply_move(m) {
+                Some(EndState::Win) => {
+                    search.stats.add_time(Phase::Expansion, start);
+                    return Iteration::Won(played);
+                }
+                Some(EndState::Defeat) => {
+                    search.stats.add_time(Phase::Expansion, start);
+                    return Iteration::Reward(0.0);
+                }
+                None => (),
+            }
+        }
+        search.stats.add_time(Phase::Expansion, start);
+        let estimate = search
+            .stats
+            .time(Phase::Heuristic, || self.heuristic.estimate(&level));
+        Iteration::Reward(1.0 / (1.0 + estimate as f64))
+    }
+
+    /// Returns the move leading to the state with the smallest estimate,
+    /// picked at random among the best ones
+    fn guided_move(&self, search: &mut Search, level: &Level) -> Move {
+        let estimates: Vec<usize> = MOVES
+            .iter()
+            .map(|&m| {
+                let mut next = level.clone();
+                match next.apply_move(m) {
+                    Some(EndState::Win) => 0,
+                    Some(EndState::Defeat) => usize::MAX,
+                    None => self.heuristic.estimate(&next),
+                }
+            })
+            .collect();
+        let smallest = *estimates.iter().min().expect("There are moves");
+        let best: Vec<Move> = MOVES
+            .iter()
+            .zip(&estimates)
+            .filter(|&(_, &estimate)| estimate == smallest)
+            .map(|(&m, _)| m)
+            .collect();
+        best[search.rng.below(best.len())]
+    }
+}
+
+impl Search<'_> {
+    fn add_node(&mut self, key: StateKey) -> usize {
+        let id = self.nodes.len();
+        self.index.insert(key.clone(), id);
+        self.nodes.push(Node {
+            key,
+            visits: 0,
+            reward: 0.0,
+            children: None,
+        });
+        id
+    }
+
+    /// Writes the generator and the nodes, the index is rebuilt from them
+    fn write(&self, writer: &mut Writer) {
+        writer.varint(self.rng.0);
+        writer.varint(self.nodes.len() as u64);
+        for node in &self.nodes {
+            writer.key(&node.key);
+            writer.varint(u64::from(node.visits));
+            writer.varint(node.reward.to_bits());
+            match &node.children {
+                Some(children) => {
+                    writer.varint(children.len() as u64 + 1);
+                    for &(m, child) in children {
+                        writer.move_(m);
+                        match child {
+                            Child::Node(id) => writer.varint(id as u64 + 1),
+                            Child::Lost => writer.varint(0),
+                        }
+                    }
+                }
+                None => writer.varint(0),
+            }
+        }
+    }
+
+    fn read(&mut self, reader: &mut Reader) -> io::Result<()> {
+        self.rng = Rng(reader.varint()?);
+        for _ in 0..reader.usize()? {
+            let id = self.add_node(reader.key()?);
+            let node = &mut self.nodes[id];
+            node.visits = reader.varint()? as u32;
+            node.reward = f64::from_bits(reader.varint()?);
+            node.children = match reader.usize()? {
+                0 => None,
+                len => Some(
+                    (1..len)
+                        .map(|_| {
+                            let m = reader.move_()?;
+                            let child = match reader.usize()? {
+                                0 => Child::Lost,
+                                id => Child::Node(id - 1),
+                            };
+                            Ok((m, child))
+                        })
+                        .collect::<io::Result<_>>()?,
+                ),
+            };
+        }
+        Ok(())
+    }
+}
+
+/// Returns the moves followed by another one
+fn with_move(moves: &[Move], m: Move) -> Vec<Move> {
+    let mut moves = moves.to_vec();
+    moves.push(m);
+    moves
+}
+
+#[test]
+fn same_seed_same_solution() {
+    use crate::solver::heuristic::WinDistance;
+
+    let level = crate::levels_list::LEVELS_LIST[0].clone();
+    let solve = |seed| {
+        MonteCarlo::new(WinDistance, seed, 100)
+            .solve(&level)
+            .outcome
+            .solution()
+            .expect("Level 1 has a solution")
+    };
+    let solution = solve(7);
+    assert_eq!(
+        level.clone().apply_move_sequence(solution.clone()),
+        Some(EndState::Win)
+    );
+    assert_eq!(solve(7), solution);
+
+    // The shortest solution takes 8 moves
+    let report = MonteCarlo::new(WinDistance, 7, 7)
+        .with_limits(Limits {
+            max_nodes: Some(2_000),
+            ..Limits::depth(7)
+        })
+        .solve(&level);
+    assert_eq!(report.outcome.solution(), None);
+}
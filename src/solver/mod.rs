@@ -0,0 +1,202 @@
+//! Solvers searching for a move sequence winning a level
+//! A state of the search is the grid of the level, the rules being parsed from it
+
+pub mod beam;
+pub mod bfs;
+pub mod bidirectional;
+pub mod certificate;
+pub mod checkpoint;
+pub mod deadlock;
+pub mod external;
+pub mod greedy;
+pub mod heuristic;
+pub mod ida_star;
+pub mod limits;
+pub mod macro_moves;
+pub mod mcts;
+pub mod optimal;
+pub mod parallel;
+pub mod portfolio;
+pub mod reachability;
+pub mod shorten;
+pub mod stats;
+pub mod symmetry;
+
+use std::collections::HashMap;
+
+use crate::interpreter::*;
+use crate::level::Level;
+use crate::square::*;
+
+/// The moves tried by the solvers, waiting is useless as nothing moves on its own
+pub const MOVES: [Move; 4] = [UP, DOWN, LEFT, RIGHT];
+
+/// A key identifying a state of a level
+pub type StateKey = Vec<Square>;
+
+/// Returns the key of the current state of a level
+pub fn state_key(level: &Level) -> StateKey {
+    level.grid.squares().to_vec()
+}
+
+/// Rebuilds a level in the state identified by the given key, the level is used
+/// as a template for the dimensions
+pub fn level_from_key(template: &Level, key: &[Square]) -> Level {
+    let mut level = template.clone();
+    for (pos, &square) in key.iter().enumerate() {
+        level.set_square(pos, square);
+    }
+    level.update_rules();
+    level
+}
+
+/// Over-approximates the squares the units tagged YOU can reach, only the grid
+/// border and the squares with a unit tagged STOP but not PUSH block them
+pub fn reachable_squares(level: &Level) -> Vec<bool> {
+    let grid = &level.grid;
+    let mut reached = vec![false; grid.squares().len()];
+    let mut stack: Vec<usize> = level
+        .units_with_property(TYOU)
+        .iter()
+        .map(|&(_, pos)| pos)
+        .collect();
+    while let Some(pos) = stack.pop() {
+        if reached[pos] {
+            continue;
+        }
+        reached[pos] = true;
+        for &m in MOVES.iter() {
+            if let Some(next) = grid.apply_move(pos, m) {
+                let blocked = level.rules.square_has_property(grid[next], TSTOP)
+                    && !level.rules.square_has_property(grid[next], TPUSH);
+                if !reached[next] && !blocked {
+                    stack.push(next);
+                }
+            }
+        }
+    }
+    reached
+}
+
+/// Returns if the rules can never change, because no text tile can be reached
+/// and then pushed by a unit tagged YOU
+pub fn rules_are_fixed(level: &Level) -> bool {
+    let reached = reachable_squares(level);
+    (0..LAYERED_SQUARES_NUMBER)
+        .map(LayeredSquare::from)
+        .filter(|layer| matches!(layer, LayeredSquare::Text(_)))
+        .all(|layer| level.grid[layer].iter().all(|&pos| !reached[pos]))
+}
+
+/// The states explored by a search, each one linked to the state it was reached from
+/// by an edge, a single move by default
+#[derive(Clone, Debug)]
+pub struct SearchTree<E = Move> {
+    /// Node -> (parent node, edge from the parent)
+    nodes: Vec<Option<(usize, E)>>,
+    index: HashMap<StateKey, usize>,
+}
+
+impl<E> Default for SearchTree<E> {
+    fn default() -> Self {
+        Self {
+            nodes: vec![],
+            index: HashMap::new(),
+        }
+    }
+}
+
+impl<E: Clone> SearchTree<E> {
+    /// Creates a tree from the root state
+    pub fn new(root: StateKey) -> Self {
+        let mut tree = Self::default();
+        tree.insert(root, None);
+        tree
+    }
+
+    /// Adds a state reached with the given edge from the given node,
+    /// returns the new node or None if the state was already explored
+    pub fn insert(&mut self, key: StateKey, parent: Option<(usize, E)>) -> Option<usize> {
+        if self.index.contains_key(&key) {
+            return None;
+        }
+        let node = self.nodes.len();
+        self.nodes.push(parent);
+        self.index.insert(key, node);
+        Some(node)
+    }
+
+    /// Links an explored state to another parent, when a better path reaches it
+    pub fn relink(&mut self, node: usize, parent: (usize, E)) {
+        self.nodes[node] = Some(parent);
+    }
+
+    /// Returns the node of an explored state
+    pub fn get(&self, key: &[Square]) -> Option<usize> {
+        self.index.get(key).cloned()
+    }
+
+    /// Number of explored states
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// The explored states, in no particular order
+    pub fn keys(&self) -> impl Iterator<Item = &StateKey> {
+        self.index.keys()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// Returns the edges leading from the root to the given node
+    pub fn path(&self, mut node: usize) -> Vec<E> {
+        let mut edges = vec![];
+        while let Some((parent, edge)) = &self.nodes[node] {
+            edges.push(edge.clone());
+            node = *parent;
+        }
+        edges.reverse();
+        edges
+    }
+}
+
+/// The move sequences explored by a search that can reach a state several times,
+/// each node is linked to the node it was reached from
+#[derive(Clone, Debug, Default)]
+pub struct PathTree {
+    nodes: Vec<Option<(usize, Move)>>,
+}
+
+impl PathTree {
+    /// Creates a tree with the root as node 0
+    pub fn new() -> Self {
+        Self { nodes: vec![None] }
+    }
+
+    /// Adds a node reached with the given move from the given node
+    pub fn push(&mut self, parent: usize, m: Move) -> usize {
+        self.nodes.push(Some((parent, m)));
+        self.nodes.len() - 1
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// Returns the moves leading from the root to the given node
+    pub fn path(&self, mut node: usize) -> Vec<Move> {
+        let mut moves = vec![];
+        while let Some((parent, m)) = self.nodes[node] {
+            moves.push(m);
+            node = parent;
+        }
+        moves.reverse();
+        moves
+    }
+}
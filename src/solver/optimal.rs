@@ -0,0 +1,365 @@
+//! Every shortest solution of a level, to tell if the intended one is the only one
+//! A breadth first search keeps all the links from a state to the states of the
+//! previous layer reaching it, so every shortest path to a state is kept. The
+//! solutions can then be counted without listing them, or grouped by the sequence
+//! of rules they go through to tell the essentially different ones apart
+//!
+//! The states equivalent by a symmetry are kept apart, their paths are different solutions
+
+use std::collections::HashMap;
+
+use crate::interpreter::*;
+use crate::level::Level;
+use crate::rules::RuleManager;
+use crate::solver::deadlock::Deadlocks;
+use crate::solver::limits::*;
+use crate::solver::reachability::RuleReachability;
+use crate::solver::stats::*;
+use crate::solver::*;
+
+pub struct AllShortest {
+    limits: Limits,
+    progress: Option<Progress>,
+}
+
+/// The outcome of a search, with every shortest solution when the level is solved
+#[derive(Clone, Debug)]
+pub struct AllShortestReport {
+    pub outcome: SolveOutcome,
+    pub stats: SearchStats,
+    pub solutions: Option<ShortestSolutions>,
+}
+
+/// A state of the layers, with every move reaching it from the previous layer
+#[derive(Clone, Debug)]
+struct Node {
+    key: StateKey,
+    depth: usize,
+    parents: Vec<(usize, Move)>,
+}
+
+/// The states on the shortest paths to a win and the links between them
+#[derive(Clone, Debug)]
+pub struct ShortestSolutions {
+    nodes: Vec<Node>,
+    /// The winning moves from the last layer
+    wins: Vec<(usize, Move)>,
+}
+
+/// The paths to a state by the numbers of the rules they go through,
+/// with the number of paths and one of them
+type Groups = HashMap<Vec<usize>, (u64, Vec<Move>)>;
+
+/// The shortest solutions going through the same sequence of rules
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RuleGroup {
+    /// The rules at the start then after each change
+    pub rules: Vec<RuleManager>,
+    pub count: u64,
+    pub example: Vec<Move>,
+}
+
+impl AllShortest {
+    /// Creates a solver giving up after `max_depth` moves
+    pub fn new(max_depth: usize) -> Self {
+        Self {
+            limits: Limits::depth(max_depth),
+            progress: None,
+        }
+    }
+
+    /// Replaces the limits of the search, including the maximum depth
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Reports the statistics of the search while it runs
+    pub fn with_progress(mut self, progress: Progress) -> Self {
+        self.progress = Some(progress);
+        self
+    }
+
+    /// Searches the layers up to the first one with a win
+    pub fn solve(&self, level: &Level) -> AllShortestReport {
+        let mut stats = SearchStats::default();
+        stats.reached(0);
+        if !RuleReachability::analyse(level).can_win() {
+            return AllShortestReport {
+                outcome: SolveOutcome::Unsolvable,
+                stats,
+                solutions: None,
+            };
+        }
+        let budget = Budget::new(&self.limits, level);
+        let mut reporter = Reporter::new(&self.progress);
+        let deadlocks = Deadlocks::new(level);
+        let mut best = Best::default();
+        best.offer(level, || 0);
+        let mut nodes = vec![Node {
+            key: state_key(level),
+            depth: 0,
+            parents: vec![],
+        }];
+        let mut ids = HashMap::new();
+        ids.insert(state_key(level), 0);
+        let mut layer = vec![0];
+
+        let limit = 'search: loop {
+            let depth = nodes[layer[0]].depth;
+            if depth >= self.limits.max_depth {
+                break Limit::Depth;
+            }
+            let mut next_layer = vec![];
+            let mut wins = vec![];
+            for &node in &layer {
+                stats.elapsed = budget.elapsed();
+                stats.memory(budget.memory(nodes.len()));
+                reporter.report(&stats);
+                if let Some(limit) = budget.exceeded(stats.expanded, nodes.len()) {
+                    break 'search limit;
+                }
+                stats.expanded += 1;
+                let current =
+                    stats.time(Phase::Expansion, || level_from_key(level, &nodes[node].key));
+                for &m in MOVES.iter() {
+                    let mut next = current.clone();
+                    let game_state = stats.time(Phase::Expansion, || next.apply_move(m));
+                    stats.generated += 1;
+                    if game_state == Some(EndState::Win) {
+                        wins.push((node, m));
+                        continue;
+                    }
+                    let key = state_key(&next);
+                    let known = stats.time(Phase::Duplicates, || ids.get(&key).cloned());
+                    if let Some(id) = known {
+                        // Another shortest path to a state of the next layer
+                        if nodes[id].depth == depth + 1 {
+                            nodes[id].parents.push((node, m));
+                        }
+                        stats.duplicates += 1;
+                        continue;
+                    }
+                    let id = nodes.len();
+                    ids.insert(key.clone(), id);
+                    nodes.push(Node {
+                        key,
+                        depth: depth + 1,
+                        parents: vec![(node, m)],
+                    });
+                    stats.reached(depth + 1);
+                    if game_state == Some(EndState::Defeat) {
+                        continue;
+                    }
+                    if stats.time(Phase::Deadlocks, || deadlocks.is_deadlocked(&next)) {
+                        stats.pruned += 1;
+                        continue;
+                    }
+                    best.offer(&next, || id);
+                    next_layer.push(id);
+                }
+            }
+
+            if !wins.is_empty() {
+                let solutions = ShortestSolutions { nodes, wins };
+                stats.elapsed = budget.elapsed();
+                return AllShortestReport {
+                    outcome: SolveOutcome::Solved(solutions.list(1).remove(0)),
+                    stats,
+                    solutions: Some(solutions),
+                };
+            }
+            if next_layer.is_empty() {
+                stats.elapsed = budget.elapsed();
+                return AllShortestReport {
+                    outcome: SolveOutcome::Unsolvable,
+                    stats,
+                    solutions: None,
+                };
+            }
+            layer = next_layer;
+        };
+
+        stats.elapsed = budget.elapsed();
+        let path = |node: usize| {
+            let mut moves = vec![];
+            let mut node = node;
+            while let Some(&(parent, m)) = nodes[node].parents.first() {
+                moves.push(m);
+                node = parent;
+            }
+            moves.reverse();
+            moves
+        };
+        AllShortestReport {
+            outcome: SolveOutcome::LimitReached {
+                limit,
+                best: best.into_partial(path),
+            },
+            stats,
+            solutions: None,
+        }
+    }
+}
+
+impl ShortestSolutions {
+    /// The number of moves of the shortest solutions
+    pub fn length(&self) -> usize {
+        self.nodes[self.wins[0].0].depth + 1
+    }
+
+    /// The number of shortest solutions, saturating at `u64::MAX`
+    pub fn count(&self) -> u64 {
+        // The nodes are in the order of their layers, after their parents
+        let mut paths = vec![0u64; self.nodes.len()];
+        paths[0] = 1;
+        for node in 1..self.nodes.len() {
+            paths[node] = self.nodes[node]
+                .parents
+                .iter()
+                .fold(0u64, |total, &(parent, _)| {
+                    total.saturating_add(paths[parent])
+                });
+        }
+        self.wins
+            .iter()
+            .fold(0u64, |total, &(node, _)| total.saturating_add(paths[node]))
+    }
+
+    /// Lists the shortest solutions, up to `max` of them
+    pub fn list(&self, max: usize) -> Vec<Vec<Move>> {
+        let mut solutions = vec![];
+        for &(node, m) in &self.wins {
+            self.list_to(node, &mut vec![m], max, &mut solutions);
+        }
+        solutions
+    }
+
+    /// Adds the paths to the node followed by the reversed moves
+    fn list_to(
+        &self,
+        node: usize,
+        suffix: &mut Vec<Move>,
+        max: usize,
+        solutions: &mut Vec<Vec<Move>>,
+    ) {
+        if solutions.len() == max {
+            return;
+        }
+        if node == 0 {
+            solutions.push(suffix.iter().rev().cloned().collect());
+            return;
+        }
+        for &(parent, m) in &self.nodes[node].parents {
+            suffix.push(m);
+            self.list_to(parent, suffix, max, solutions);
+            suffix.pop();
+        }
+    }
+
+    /// Groups the shortest solutions by the sequence of rules they go through,
+    /// the groups with the most solutions first
+    pub fn group_by_rules(&self, level: &Level) -> Vec<RuleGroup> {
+        // The rules met are numbered in order
+        let mut rules: Vec<RuleManager> = vec![];
+        let mut intern = |state: &Level| match rules.iter().position(|r| *r == state.rules) {
+            Some(id) => id,
+            None => {
+                rules.push(state.rules.clone());
+                rules.len() - 1
+            }
+        };
+        let extend = |sequence: &[usize], id: usize| {
+            let mut sequence = sequence.to_vec();
+            if sequence.last() != Some(&id) {
+                sequence.push(id);
+            }
+            sequence
+        };
+        let mut groups: Vec<Groups> = vec![HashMap::new(); self.nodes.len()];
+        groups[0].insert(vec![intern(level)], (1, vec![]));
+        for node in 1..self.nodes.len() {
+            let id = intern(&level_from_key(level, &self.nodes[node].key));
+            for &(parent, m) in &self.nodes[node].parents {
+                let incoming: Vec<_> = groups[parent]
+                    .iter()
+                    .map(|(sequence, (count, example))| {
+                        (extend(sequence, id), *count, example.clone())
+                    })
+                    .collect();
+                for (sequence, count, example) in incoming {
+                    add(&mut groups[node], sequence, count, &example, m);
+                }
+            }
+        }
+        let mut won = HashMap::new();
+        for &(node, m) in &self.wins {
+            let mut state = level_from_key(level, &self.nodes[node].key);
+            state.apply_move(m);
+            let id = intern(&state);
+            for (sequence, (count, example)) in &groups[node] {
+                add(&mut won, extend(sequence, id), *count, example, m);
+            }
+        }
+
+        let mut groups: Vec<RuleGroup> = won
+            .into_iter()
+            .map(|(sequence, (count, example))| RuleGroup {
+                rules: sequence.iter().map(|&id| rules[id].clone()).collect(),
+                count,
+                example,
+            })
+            .collect();
+        groups.sort_by(|a, b| {
+            b.count
+                .cmp(&a.count)
+                .then_with(|| a.example.cmp(&b.example))
+        });
+        groups
+    }
+}
+
+/// Adds paths followed by a move to a group
+fn add(groups: &mut Groups, sequence: Vec<usize>, count: u64, example: &[Move], m: Move) {
+    let group = groups.entry(sequence).or_insert_with(|| {
+        let mut example = example.to_vec();
+        example.push(m);
+        (0, example)
+    });
+    group.0 = group.0.saturating_add(count);
+}
+
+#[test]
+fn counts_the_paths_to_the_flag() {
+    use crate::level::*;
+    use crate::square::*;
+
+    let level = crate::levels_list::LEVELS_LIST[0].clone();
+    let solutions = AllShortest::new(20).solve(&level).solutions;
+    let solutions = solutions.expect("Level 1 has a solution");
+    assert_eq!(solutions.count(), 1);
+    assert_eq!(solutions.list(10), vec![vec![RIGHT; 8]]);
+
+    // Any order of 3 moves down and 3 moves right reaches the flag
+    let mut level = Level::new(7, 7);
+    level.add_rule(&[TBABA, TIS, TYOU], (0, 0), HORIZONTAL);
+    level.add_rule(&[TFLAG, TIS, TWIN], (0, 1), HORIZONTAL);
+    level.add_square(Entity::BABA.into(), (3, 3));
+    level.add_square(Entity::FLAG.into(), (6, 6));
+    let solutions = AllShortest::new(20).solve(&level).solutions;
+    let solutions = solutions.expect("The flag can be reached");
+    assert_eq!(solutions.length(), 6);
+    assert_eq!(solutions.count(), 20);
+    let listed = solutions.list(100);
+    assert_eq!(listed.len(), 20);
+    for moves in listed {
+        assert_eq!(
+            level.clone().apply_move_sequence(moves),
+            Some(EndState::Win)
+        );
+    }
+    let groups = solutions.group_by_rules(&level);
+    assert_eq!(groups.len(), 1);
+    assert_eq!(groups[0].count, 20);
+    assert_eq!(groups[0].rules, vec![level.rules.clone()]);
+}
@@ -0,0 +1,424 @@
+//! A breadth first solver spreading the expansion of each layer over several threads
+//! The explored states are kept in a set sharded by state hash, each shard behind its
+//! own lock. The layers are expanded one after the other, so the solution found is
+//! as short as the one of the single-threaded breadth first search.
+//! The checkpoints are saved between the expansions of two layers. An interruption
+//! stops the threads in the middle of a layer, the last checkpoint is then kept
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Instant;
+
+use crate::interpreter::*;
+use crate::level::Level;
+use crate::solver::checkpoint::*;
+use crate::solver::deadlock::Deadlocks;
+use crate::solver::limits::*;
+use crate::solver::reachability::RuleReachability;
+use crate::solver::stats::*;
+use crate::solver::*;
+
+/// Number of shards per thread, to keep the lock contention low
+const SHARDS_PER_THREAD: usize = 16;
+
+pub struct ParallelBfs {
+    limits: Limits,
+    progress: Option<Progress>,
+    threads: usize,
+}
+
+/// A node of the sharded tree as (shard, index in the shard)
+type NodeId = (usize, usize);
+
+/// The result of expanding a part of a layer
+struct Expansion {
+    next_layer: Vec<(NodeId, StateKey)>,
+    win: Option<NodeId>,
+    /// The interruption or the limit hit in the middle of the layer
+    stop: Option<Stop>,
+    best: Best<NodeId>,
+    stats: SearchStats,
+}
+
+/// The state shared by the search threads
+struct Shared<'a> {
+    level: &'a Level,
+    tree: ShardedTree,
+    budget: Budget,
+    deadlocks: Deadlocks,
+    expanded: AtomicU64,
+    /// Set when a thread found a solution, hit a limit or was interrupted
+    stop: AtomicBool,
+    /// Stops the threads when the process is interrupted
+    interruptible: bool,
+}
+
+/// A part of the explored states, each one linked to the state it was reached from
+#[derive(Default)]
+struct Shard {
+    nodes: Vec<Option<(NodeId, Move)>>,
+    index: HashMap<StateKey, usize>,
+}
+
+/// The explored states, sharded by state hash
+struct ShardedTree {
+    shards: Vec<Mutex<Shard>>,
+    len: AtomicUsize,
+}
+
+impl ShardedTree {
+    fn new(shards: usize) -> Self {
+        Self {
+            shards: (0..shards).map(|_| Mutex::new(Shard::default())).collect(),
+            len: AtomicUsize::new(0),
+        }
+    }
+
+    /// Adds a state reached with the given move from the given node,
+    /// returns the new node or None if the state was already explored
+    fn insert(&self, key: &StateKey, parent: Option<(NodeId, Move)>) -> Option<NodeId> {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        let shard_index = hasher.finish() as usize % self.shards.len();
+
+        let mut shard = self.shards[shard_index].lock().unwrap();
+        if shard.index.contains_key(key) {
+            return None;
+        }
+        let node = shard.nodes.len();
+        shard.nodes.push(parent);
+        shard.index.insert(key.clone(), node);
+        self.len.fetch_add(1, Ordering::Relaxed);
+        Some((shard_index, node))
+    }
+
+    /// Returns the moves leading from the root to the given node
+    fn path(&self, mut node: NodeId) -> Vec<Move> {
+        let mut moves = vec![];
+        while let Some((parent, m)) = self.shards[node.0].lock().unwrap().nodes[node.1] {
+            moves.push(m);
+            node = parent;
+        }
+        moves.reverse();
+        moves
+    }
+
+    fn read(reader: &mut Reader) -> io::Result<Self> {
+        let tree = Self::new(reader.usize()?);
+        for shard in &tree.shards {
+            let mut shard = shard.lock().unwrap();
+            for _ in 0..reader.usize()? {
+                let parent = match reader.usize()? {
+                    0 => None,
+                    _ => Some(((reader.usize()?, reader.usize()?), reader.move_()?)),
+                };
+                shard.nodes.push(parent);
+            }
+            for _ in 0..shard.nodes.len() {
+                let node = reader.usize()?;
+                shard.index.insert(reader.key()?, node);
+            }
+            tree.len.fetch_add(shard.nodes.len(), Ordering::Relaxed);
+        }
+        Ok(tree)
+    }
+
+    fn write(&self, writer: &mut Writer) {
+        writer.varint(self.shards.len() as u64);
+        for shard in &self.shards {
+            let shard = shard.lock().unwrap();
+            writer.varint(shard.nodes.len() as u64);
+            for parent in &shard.nodes {
+                match parent {
+                    Some(((shard, node), m)) => {
+                        writer.varint(1);
+                        writer.varint(*shard as u64);
+                        writer.varint(*node as u64);
+                        writer.move_(*m);
+                    }
+                    None => writer.varint(0),
+                }
+            }
+            for (key, &node) in &shard.index {
+                writer.varint(node as u64);
+                writer.key(key);
+            }
+        }
+    }
+}
+
+impl ParallelBfs {
+    /// Creates a solver running on the given number of threads
+    /// and giving up after `max_depth` moves
+    pub fn new(max_depth: usize, threads: usize) -> Self {
+        Self {
+            limits: Limits::depth(max_depth),
+            progress: None,
+            threads: threads.max(1),
+        }
+    }
+
+    /// Replaces the limits of the search, including the maximum depth
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Reports the statistics of the search between two layers. The time of the
+    /// phases is summed over the threads
+    pub fn with_progress(mut self, progress: Progress) -> Self {
+        self.progress = Some(progress);
+        self
+    }
+
+    /// Number of threads available on this machine
+    pub fn available_threads() -> usize {
+        thread::available_parallelism().map_or(1, |n| n.get())
+    }
+
+    /// Searches a shortest winning move sequence
+    pub fn solve(&self, level: &Level) -> SolveReport {
+        self.search(level, None)
+            .expect("No I/O without checkpoints")
+    }
+
+    /// Searches a shortest winning move sequence, saving checkpoints of the search.
+    /// A search stopped at a limit between two layers saves a checkpoint it can be
+    /// resumed from with greater limits, one stopped in the middle of a layer keeps
+    /// its last checkpoint. A resumed search keeps the number of shards of the checkpoint
+    pub fn solve_with_checkpoints(
+        &self,
+        level: &Level,
+        config: CheckpointConfig,
+    ) -> io::Result<SolveReport> {
+        self.search(level, Some(Checkpointer::new(config, level, "parallel")))
+    }
+
+    fn search(
+        &self,
+        level: &Level,
+        mut checkpointer: Option<Checkpointer>,
+    ) -> io::Result<SolveReport> {
+        let saved = match &checkpointer {
+            Some(checkpointer) => checkpointer.load()?,
+            None => None,
+        };
+        let (mut stats, tree, mut layer, mut depth) = match saved {
+            Some(bytes) => {
+                let mut reader = Reader::for_level(&bytes, level);
+                let stats = reader.stats()?;
+                let tree = ShardedTree::read(&mut reader)?;
+                let mut layer = vec![];
+                for _ in 0..reader.usize()? {
+                    layer.push(((reader.usize()?, reader.usize()?), reader.key()?));
+                }
+                (stats, tree, layer, reader.usize()?)
+            }
+            None => {
+                let tree = ShardedTree::new(self.threads * SHARDS_PER_THREAD);
+                let root = state_key(level);
+                let layer = match tree.insert(&root, None) {
+                    Some(node) => vec![(node, root)],
+                    None => vec![],
+                };
+                let mut stats = SearchStats::default();
+                stats.reached(0);
+                (stats, tree, layer, 0)
+            }
+        };
+        if !RuleReachability::analyse(level).can_win() {
+            return Ok(SolveReport {
+                outcome: SolveOutcome::Unsolvable,
+                stats,
+            });
+        }
+        let (start, resumed_elapsed) = (Instant::now(), stats.elapsed);
+        let shared = Shared {
+            level,
+            tree,
+            budget: Budget::new(&self.limits, level),
+            deadlocks: Deadlocks::new(level),
+            expanded: AtomicU64::new(stats.expanded),
+            stop: AtomicBool::new(false),
+            interruptible: checkpointer.is_some(),
+        };
+        let mut reporter = Reporter::new(&self.progress);
+        let mut best = Best::default();
+
+        loop {
+            if layer.is_empty() {
+                return Ok(SolveReport {
+                    outcome: SolveOutcome::Unsolvable,
+                    stats,
+                });
+            }
+            let states = shared.tree.len.load(Ordering::Relaxed);
+            stats.elapsed = resumed_elapsed + start.elapsed();
+            stats.memory(shared.budget.memory(states));
+            reporter.report(&stats);
+            let stop = if depth >= self.limits.max_depth {
+                Some(Limit::Depth)
+            } else {
+                shared.budget.exceeded(stats.expanded, states)
+            };
+
+            let saving = Instant::now();
+            let write = |writer: &mut Writer| {
+                writer.stats(&stats);
+                shared.tree.write(writer);
+                writer.varint(layer.len() as u64);
+                for ((shard, node), key) in &layer {
+                    writer.varint(*shard as u64);
+                    writer.varint(*node as u64);
+                    writer.key(key);
+                }
+                writer.varint(depth as u64);
+            };
+            if let Some(checkpointer) = &mut checkpointer {
+                if stop.is_some() {
+                    checkpointer.save(write)?;
+                } else {
+                    checkpointer.save_if_due(write)?;
+                }
+            }
+            stats.add_time(Phase::Checkpoints, saving);
+
+            let mut stop = stop.map(Stop::Limit);
+            if stop.is_none() {
+                let chunk_size = (layer.len() + self.threads - 1) / self.threads;
+                let shared = &shared;
+                let results: Vec<Expansion> = thread::scope(|scope| {
+                    let workers: Vec<_> = layer
+                        .chunks(chunk_size)
+                        .map(|chunk| scope.spawn(move || expand(shared, chunk, depth + 1)))
+                        .collect();
+                    workers
+                        .into_iter()
+                        .map(|worker| worker.join().expect("A search thread panicked"))
+                        .collect()
+                });
+
+                layer = vec![];
+                let mut win = None;
+                for expansion in results {
+                    stats.merge(&expansion.stats);
+                    win = win.or(expansion.win);
+                    stop = stop.or(expansion.stop);
+                    best.merge(expansion.best);
+                    layer.extend(expansion.next_layer);
+                }
+                if let Some(win) = win {
+                    return Ok(SolveReport {
+                        outcome: SolveOutcome::Solved(shared.tree.path(win)),
+                        stats,
+                    });
+                }
+                depth += 1;
+            }
+
+            if let Some(stop) = stop {
+                let limit = match stop {
+                    // The tree holds a part of the layer, it can't be saved
+                    Stop::Interrupted => return Err(interruption()),
+                    Stop::Limit(limit) => limit,
+                };
+                let best = best.into_partial(|node| shared.tree.path(node));
+                return Ok(SolveReport {
+                    outcome: SolveOutcome::LimitReached { limit, best },
+                    stats,
+                });
+            }
+        }
+    }
+}
+
+/// Expands a part of a layer, the new states are at the given depth
+fn expand(shared: &Shared, chunk: &[(NodeId, StateKey)], depth: usize) -> Expansion {
+    let mut expansion = Expansion {
+        next_layer: vec![],
+        win: None,
+        stop: None,
+        best: Best::default(),
+        stats: SearchStats::default(),
+    };
+    let stats = &mut expansion.stats;
+    for (node, key) in chunk {
+        // Another thread already found a solution, hit a limit or was interrupted
+        if shared.stop.load(Ordering::Relaxed) {
+            break;
+        }
+        if shared.interruptible && interrupted() {
+            shared.stop.store(true, Ordering::Relaxed);
+            expansion.stop = Some(Stop::Interrupted);
+            break;
+        }
+        let expanded = shared.expanded.fetch_add(1, Ordering::Relaxed);
+        let states = shared.tree.len.load(Ordering::Relaxed);
+        if let Some(limit) = shared.budget.exceeded(expanded, states) {
+            shared.stop.store(true, Ordering::Relaxed);
+            expansion.stop = Some(Stop::Limit(limit));
+            break;
+        }
+        stats.expanded += 1;
+
+        let current = stats.time(Phase::Expansion, || level_from_key(shared.level, key));
+        for &m in MOVES.iter() {
+            let mut next = current.clone();
+            let game_state = stats.time(Phase::Expansion, || next.apply_move(m));
+            let next_key = state_key(&next);
+            stats.generated += 1;
+            let inserted = stats.time(Phase::Duplicates, || {
+                shared.tree.insert(&next_key, Some((*node, m)))
+            });
+            let child = match inserted {
+                Some(child) => child,
+                None => {
+                    stats.duplicates += 1;
+                    continue;
+                }
+            };
+            stats.reached(depth);
+            match game_state {
+                Some(EndState::Win) => {
+                    shared.stop.store(true, Ordering::Relaxed);
+                    expansion.win = Some(child);
+                    return expansion;
+                }
+                Some(EndState::Defeat) => (),
+                None if stats.time(Phase::Deadlocks, || shared.deadlocks.is_deadlocked(&next)) => {
+                    stats.pruned += 1;
+                }
+                None => {
+                    expansion.best.offer(&next, || child);
+                    expansion.next_layer.push((child, next_key));
+                }
+            }
+        }
+    }
+    expansion
+}
+
+#[test]
+fn same_length_as_bfs() {
+    let level = crate::levels_list::LEVELS_LIST[0].clone();
+    let shortest = crate::solver::bfs::Bfs::new(20)
+        .solve(&level)
+        .outcome
+        .solution()
+        .unwrap();
+    let solution = ParallelBfs::new(20, 4)
+        .solve(&level)
+        .outcome
+        .solution()
+        .unwrap();
+    assert_eq!(solution.len(), shortest.len());
+    assert_eq!(
+        level.clone().apply_move_sequence(solution),
+        Some(EndState::Win)
+    );
+}
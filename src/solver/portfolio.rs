@@ -0,0 +1,441 @@
+//! A portfolio solver racing several solvers on their own threads
+//! The first strategy to solve the level or to prove it unsolvable wins and the
+//! others are cancelled. A strategy stopped by one of its limits doesn't end the race,
+//! nor does a solution that doesn't win when replayed
+//!
+//! The default portfolio is picked from features of the level: an exhaustive search
+//! on small grids, a beam on large ones, and rollouts when many texts make the rules,
+//! and so the heuristics, change a lot
+//!
+//! Each strategy saves its checkpoints in its own file, named after the checkpoint
+//! file of the portfolio followed by the name of the strategy
+
+use std::fmt;
+use std::io;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use crate::encoding::invalid;
+use crate::interpreter::EndState;
+use crate::level::Level;
+use crate::solver::beam::*;
+use crate::solver::bfs::Bfs;
+use crate::solver::bidirectional::Bidirectional;
+use crate::solver::checkpoint::*;
+use crate::solver::greedy::GreedyBestFirst;
+use crate::solver::heuristic::WinDistance;
+use crate::solver::ida_star::*;
+use crate::solver::limits::*;
+use crate::solver::macro_moves::MacroBfs;
+use crate::solver::mcts::MonteCarlo;
+use crate::solver::stats::*;
+use crate::solver::*;
+use crate::square::*;
+
+/// Grids up to this number of squares are searched exhaustively
+const SMALL_GRID: usize = 256;
+/// From this number of text tiles, the rules are expected to change a lot
+const MANY_TEXTS: usize = 12;
+/// Seed of the random moves of the Monte Carlo strategy
+const MCTS_SEED: u64 = 0;
+/// Interval between two checks of the cancellation of the whole race
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// A solver of the portfolio, guided solvers use the win distance
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Strategy {
+    Bfs,
+    IdaStar,
+    Beam,
+    Greedy,
+    Bidirectional,
+    Macro,
+    Mcts,
+}
+
+pub const STRATEGIES: [Strategy; 7] = [
+    Strategy::Bfs,
+    Strategy::IdaStar,
+    Strategy::Beam,
+    Strategy::Greedy,
+    Strategy::Bidirectional,
+    Strategy::Macro,
+    Strategy::Mcts,
+];
+
+impl Strategy {
+    /// The name of the solver on the command line
+    pub fn name(self) -> &'static str {
+        match self {
+            Strategy::Bfs => "bfs",
+            Strategy::IdaStar => "ida",
+            Strategy::Beam => "beam",
+            Strategy::Greedy => "greedy",
+            Strategy::Bidirectional => "bidirectional",
+            Strategy::Macro => "macro",
+            Strategy::Mcts => "mcts",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        STRATEGIES.iter().cloned().find(|s| s.name() == name)
+    }
+
+    /// Runs the solver of the strategy
+    pub fn solve(self, level: &Level, limits: Limits) -> SolveReport {
+        self.run(level, limits, None)
+            .expect("No I/O without checkpoints")
+    }
+
+    /// Runs the solver of the strategy, saving checkpoints of its search
+    pub fn solve_with_checkpoints(
+        self,
+        level: &Level,
+        limits: Limits,
+        config: CheckpointConfig,
+    ) -> io::Result<SolveReport> {
+        self.run(level, limits, Some(config))
+    }
+
+    fn run(
+        self,
+        level: &Level,
+        limits: Limits,
+        config: Option<CheckpointConfig>,
+    ) -> io::Result<SolveReport> {
+        let max_depth = limits.max_depth;
+        match self {
+            Strategy::Bfs => {
+                let solver = Bfs::new(max_depth).with_limits(limits);
+                checkpointed(
+                    config,
+                    || solver.solve(level),
+                    |config| solver.solve_with_checkpoints(level, config),
+                )
+            }
+            Strategy::IdaStar => {
+                let solver =
+                    IdaStar::new(WinDistance, DEFAULT_TABLE_SIZE, max_depth).with_limits(limits);
+                let result = checkpointed(
+                    config,
+                    || solver.solve(level),
+                    |config| solver.solve_with_checkpoints(level, config),
+                )?;
+                Ok(SolveReport {
+                    outcome: result.outcome,
+                    stats: result.stats,
+                })
+            }
+            Strategy::Beam => {
+                let solver =
+                    BeamSearch::new(WinDistance, DEFAULT_BEAM_WIDTH, max_depth).with_limits(limits);
+                checkpointed(
+                    config,
+                    || solver.solve(level),
+                    |config| solver.solve_with_checkpoints(level, config),
+                )
+            }
+            Strategy::Greedy => {
+                let solver = GreedyBestFirst::new(WinDistance, max_depth).with_limits(limits);
+                checkpointed(
+                    config,
+                    || solver.solve(level),
+                    |config| solver.solve_with_checkpoints(level, config),
+                )
+            }
+            Strategy::Bidirectional => {
+                let solver = Bidirectional::new(max_depth).with_limits(limits);
+                checkpointed(
+                    config,
+                    || solver.solve(level),
+                    |config| solver.solve_with_checkpoints(level, config),
+                )
+            }
+            Strategy::Macro => {
+                let solver = MacroBfs::new(max_depth).with_limits(limits);
+                checkpointed(
+                    config,
+                    || solver.solve(level),
+                    |config| solver.solve_with_checkpoints(level, config),
+                )
+            }
+            Strategy::Mcts => {
+                let solver = MonteCarlo::new(WinDistance, MCTS_SEED, max_depth).with_limits(limits);
+                checkpointed(
+                    config,
+                    || solver.solve(level),
+                    |config| solver.solve_with_checkpoints(level, config),
+                )
+            }
+        }
+    }
+}
+
+/// Runs a search, with checkpoints if they are configured
+fn checkpointed<T>(
+    config: Option<CheckpointConfig>,
+    solve: impl FnOnce() -> T,
+    solve_with_checkpoints: impl FnOnce(CheckpointConfig) -> io::Result<T>,
+) -> io::Result<T> {
+    match config {
+        Some(config) => solve_with_checkpoints(config),
+        None => Ok(solve()),
+    }
+}
+
+impl fmt::Display for Strategy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad(self.name())
+    }
+}
+
+/// The features of a level the default portfolio is picked from
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Features {
+    pub squares: usize,
+    pub texts: usize,
+    pub fixed_rules: bool,
+}
+
+impl Features {
+    pub fn new(level: &Level) -> Self {
+        let texts = (0..LAYERED_SQUARES_NUMBER)
+            .map(LayeredSquare::from)
+            .filter(|layer| matches!(layer, LayeredSquare::Text(_)))
+            .map(|layer| level.grid[layer].len())
+            .sum();
+        Self {
+            squares: level.grid.squares().len(),
+            texts,
+            fixed_rules: rules_are_fixed(level),
+        }
+    }
+
+    /// Returns the strategies expected to do well on the level
+    pub fn default_portfolio(&self) -> Vec<Strategy> {
+        let mut strategies = vec![Strategy::IdaStar, Strategy::Greedy];
+        if self.squares <= SMALL_GRID {
+            strategies.push(Strategy::Bfs);
+        } else {
+            strategies.push(Strategy::Beam);
+        }
+        if self.fixed_rules {
+            strategies.push(Strategy::Bidirectional);
+        }
+        if self.texts >= MANY_TEXTS {
+            strategies.push(Strategy::Mcts);
+        }
+        strategies
+    }
+}
+
+/// The outcome of a race with the strategy that won it, None if every strategy
+/// stopped at a limit. The statistics of all the strategies are added up
+#[derive(Clone, Debug)]
+pub struct PortfolioReport {
+    pub outcome: SolveOutcome,
+    pub stats: SearchStats,
+    pub winner: Option<Strategy>,
+}
+
+pub struct PortfolioSolver {
+    strategies: Vec<Strategy>,
+    limits: Limits,
+}
+
+impl PortfolioSolver {
+    /// Creates a solver racing the given strategies, giving up after `max_depth` moves.
+    /// Panics without any strategy
+    pub fn new(strategies: Vec<Strategy>, max_depth: usize) -> Self {
+        assert!(
+            !strategies.is_empty(),
+            "A portfolio races at least one strategy"
+        );
+        Self {
+            strategies,
+            limits: Limits::depth(max_depth),
+        }
+    }
+
+    /// Creates a solver racing the default portfolio of the level
+    pub fn for_level(level: &Level, max_depth: usize) -> Self {
+        Self::new(Features::new(level).default_portfolio(), max_depth)
+    }
+
+    /// Replaces the limits of every strategy. The memory limit is shared
+    /// evenly between them
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    pub fn strategies(&self) -> &[Strategy] {
+        &self.strategies
+    }
+
+    /// Races the strategies, panics if every strategy ended with a solution that doesn't win
+    pub fn solve(&self, level: &Level) -> PortfolioReport {
+        self.race(level, None)
+            .expect("Only wrong solutions fail a race without checkpoints")
+    }
+
+    /// Races the strategies, each one saving checkpoints of its search in its own file.
+    /// An interruption or an error of a strategy stops the race once every strategy
+    /// saved its checkpoint
+    pub fn solve_with_checkpoints(
+        &self,
+        level: &Level,
+        config: CheckpointConfig,
+    ) -> io::Result<PortfolioReport> {
+        self.race(level, Some(config))
+    }
+
+    fn race(&self, level: &Level, config: Option<CheckpointConfig>) -> io::Result<PortfolioReport> {
+        let budget = Budget::new(&self.limits, level);
+        // Cancelled when the race is over, or when the whole race is cancelled
+        let race = CancelToken::new();
+        let limits = Limits {
+            max_memory: self
+                .limits
+                .max_memory
+                .map(|max| max / self.strategies.len()),
+            cancel: race.clone(),
+            ..self.limits.clone()
+        };
+
+        let mut stats = SearchStats::default();
+        let mut winner = None;
+        let mut error = None;
+        // The best state of the strategies stopped at a limit
+        let mut stopped: Option<(Limit, Option<PartialState>)> = None;
+        thread::scope(|scope| {
+            let (sender, receiver) = mpsc::channel();
+            for &strategy in &self.strategies {
+                let (sender, limits) = (sender.clone(), limits.clone());
+                let config = config
+                    .as_ref()
+                    .map(|config| strategy_checkpoint(config, strategy));
+                scope.spawn(move || {
+                    // The receiver waits for every strategy
+                    let _ = sender.send((strategy, strategy.run(level, limits, config)));
+                });
+            }
+            drop(sender);
+
+            loop {
+                let (strategy, report) = match receiver.recv_timeout(POLL_INTERVAL) {
+                    Ok(result) => result,
+                    Err(mpsc::RecvTimeoutError::Timeout) => {
+                        if self.limits.cancel.is_cancelled() {
+                            race.cancel();
+                        }
+                        continue;
+                    }
+                    Err(mpsc::RecvTimeoutError::Disconnected) => break,
+                };
+                let report = match report {
+                    Ok(report) => report,
+                    Err(e) => {
+                        race.cancel();
+                        if error.is_none() {
+                            error = Some(e);
+                        }
+                        continue;
+                    }
+                };
+                stats.merge(&report.stats);
+                match report.outcome {
+                    SolveOutcome::LimitReached { limit, best } => {
+                        let closer = match (&stopped, &best) {
+                            (None, _) => true,
+                            (Some((_, None)), Some(_)) => true,
+                            (Some((_, Some(kept))), Some(best)) => {
+                                best.win_distance < kept.win_distance
+                            }
+                            _ => false,
+                        };
+                        if closer {
+                            stopped = Some((limit, best));
+                        }
+                    }
+                    // A wrong solution is left out of the race
+                    SolveOutcome::Solved(moves)
+                        if level.clone().apply_move_sequence(moves.clone())
+                            != Some(EndState::Win) => {}
+                    outcome => {
+                        if winner.is_none() {
+                            race.cancel();
+                            winner = Some((strategy, outcome));
+                        }
+                    }
+                }
+            }
+        });
+        stats.elapsed = budget.elapsed();
+        if let Some(error) = error {
+            return Err(error);
+        }
+
+        let (winner, outcome) = match (winner, stopped) {
+            (Some((strategy, outcome)), _) => (Some(strategy), outcome),
+            (None, Some((limit, best))) => {
+                let limit = if self.limits.cancel.is_cancelled() {
+                    Limit::Cancelled
+                } else {
+                    limit
+                };
+                (None, SolveOutcome::LimitReached { limit, best })
+            }
+            // Every strategy ended with a solution that doesn't win
+            (None, None) => return Err(invalid("no strategy found a winning solution")),
+        };
+        Ok(PortfolioReport {
+            outcome,
+            stats,
+            winner,
+        })
+    }
+}
+
+/// The checkpoints of a strategy, in the checkpoint file of the portfolio
+/// followed by the name of the strategy
+fn strategy_checkpoint(config: &CheckpointConfig, strategy: Strategy) -> CheckpointConfig {
+    let mut path = config.path.clone().into_os_string();
+    path.push(".");
+    path.push(strategy.name());
+    CheckpointConfig {
+        path: path.into(),
+        ..config.clone()
+    }
+}
+
+#[test]
+fn the_first_conclusive_strategy_wins() {
+    use crate::interpreter::EndState;
+
+    let level = crate::levels_list::LEVELS_LIST[0].clone();
+    let features = Features::new(&level);
+    assert_eq!(features.squares, 15 * 11);
+    assert!(features.default_portfolio().contains(&Strategy::Bfs));
+
+    let report = PortfolioSolver::for_level(&level, 100).solve(&level);
+    let winner = report.winner.expect("Level 1 has a solution");
+    assert!(features.default_portfolio().contains(&winner));
+    let solution = report.outcome.solution().expect("Level 1 has a solution");
+    assert_eq!(
+        level.clone().apply_move_sequence(solution),
+        Some(EndState::Win)
+    );
+
+    // A search stopped at its depth limit doesn't win the race
+    let report = PortfolioSolver::new(vec![Strategy::Bfs, Strategy::Greedy], 3).solve(&level);
+    assert_eq!(report.winner, None);
+    assert!(matches!(
+        report.outcome,
+        SolveOutcome::LimitReached {
+            limit: Limit::Depth,
+            ..
+        }
+    ));
+}
@@ -0,0 +1,223 @@
+//! An over-approximation of the rules that can ever be formed in a level
+//! The units that can ever be YOU flood the grid up to the walls that stay STOP forever,
+//! the text tiles they can reach are mobile and could be pushed anywhere in that region,
+//! the other ones never move. A rule is formable if its words can be lined up, and
+//! permanent if it is written with text tiles that never move
+//!
+//! The walls and the mobile tiles depend on each other, so the analysis starts from
+//! the current rules and iterates: the formable rules only grow, the permanent ones
+//! only shrink, until neither changes
+
+use crate::grid::Grid;
+use crate::level::Level;
+use crate::rules::RuleManager;
+use crate::solver::MOVES;
+use crate::square::*;
+
+/// The rules that can form in a level, the permanent ones are also formable
+pub struct RuleReachability {
+    pub formable: RuleManager,
+    /// Rules that are active in every state
+    pub permanent: RuleManager,
+}
+
+/// A text tile and whether it can be pushed
+struct Tile {
+    text: Text,
+    pos: usize,
+    mobile: bool,
+}
+
+impl RuleReachability {
+    pub fn analyse(level: &Level) -> Self {
+        let mut analysis = Self {
+            formable: level.rules.clone(),
+            permanent: level.rules.clone(),
+        };
+        loop {
+            let region = analysis.mobile_region(level);
+            let tiles = text_tiles(level, &region);
+
+            // Only the rules still written by the tiles that never move stay permanent
+            let mut fixed = level.clone();
+            for tile in tiles.iter().filter(|tile| tile.mobile) {
+                fixed.remove_layer_internal(LayeredSquare::from(tile.text), tile.pos);
+            }
+            let written = RuleManager::parse(&fixed.grid);
+            let mut permanent = analysis.permanent.clone();
+            for (entity, text) in analysis.permanent.active_rules() {
+                permanent[entity][usize::from(text)] = written[entity][usize::from(text)];
+            }
+
+            let mut formable = analysis.formable.clone();
+            for (entity, text) in formable_rules(&level.grid, &tiles, &region) {
+                formable.add_rule(entity, text);
+            }
+
+            if permanent == analysis.permanent && formable == analysis.formable {
+                return analysis;
+            }
+            analysis = Self {
+                formable,
+                permanent,
+            };
+        }
+    }
+
+    /// Returns if a rule can make something YOU and a rule can make something WIN,
+    /// the level can't be won otherwise
+    pub fn can_win(&self) -> bool {
+        let some = |property: Text| {
+            ENTITIES
+                .iter()
+                .any(|&entity| self.formable[entity][usize::from(property)])
+        };
+        some(TYOU) && some(TWIN)
+    }
+
+    /// Returns if the entity can turn into an entity that can be YOU
+    fn can_be_you(&self, entity: Entity) -> bool {
+        let mut you = vec![entity];
+        let mut i = 0;
+        while let Some(&current) = you.get(i) {
+            if self.formable[current][usize::from(TYOU)] {
+                return true;
+            }
+            for &target in ENTITIES.iter() {
+                if self.formable[current][usize::from(Text::from(target))] && !you.contains(&target)
+                {
+                    you.push(target);
+                }
+            }
+            i += 1;
+        }
+        false
+    }
+
+    /// Returns if the units of the entity always stay in place and are always STOP
+    fn is_wall(&self, entity: Entity) -> bool {
+        let transforms = ENTITIES.iter().any(|&target| {
+            target != entity && self.formable[entity][usize::from(Text::from(target))]
+        });
+        self.permanent[entity][usize::from(TSTOP)]
+            && !self.formable[entity][usize::from(TPUSH)]
+            && !transforms
+            && !self.can_be_you(entity)
+    }
+
+    /// Over-approximates the squares the units that can be YOU can ever reach
+    fn mobile_region(&self, level: &Level) -> Vec<bool> {
+        let grid = &level.grid;
+        let walls: Vec<bool> = ENTITIES.iter().map(|&e| self.is_wall(e)).collect();
+        let you: Vec<bool> = ENTITIES.iter().map(|&e| self.can_be_you(e)).collect();
+        let mut reached = vec![false; grid.squares().len()];
+        let mut stack: Vec<usize> = (0..reached.len())
+            .filter(|&pos| grid[pos].into_iter().any(|l| you[Entity::from(l) as usize]))
+            .collect();
+        while let Some(pos) = stack.pop() {
+            if reached[pos] {
+                continue;
+            }
+            reached[pos] = true;
+            for &m in MOVES.iter() {
+                if let Some(next) = grid.apply_move(pos, m) {
+                    let blocked = grid[next]
+                        .into_iter()
+                        .any(|l| walls[Entity::from(l) as usize]);
+                    if !reached[next] && !blocked {
+                        stack.push(next);
+                    }
+                }
+            }
+        }
+        reached
+    }
+}
+
+/// Lists the text tiles of the level, the ones in the region are mobile
+fn text_tiles(level: &Level, region: &[bool]) -> Vec<Tile> {
+    (0..TEXTS_NUMBER)
+        .map(Text::from)
+        .flat_map(|text| {
+            level.grid[LayeredSquare::from(text)]
+                .iter()
+                .map(move |&pos| Tile {
+                    text,
+                    pos,
+                    mobile: region[pos],
+                })
+        })
+        .collect()
+}
+
+/// Returns the `ENTITY IS ENTITY|PROPERTY` rules whose tiles can be lined up.
+/// Chains of AND are not followed: if an AND tile can move, any rule with a
+/// mobile tile is considered formable
+fn formable_rules(grid: &Grid, tiles: &[Tile], region: &[bool]) -> Vec<(Entity, Text)> {
+    let mobile_and = tiles.iter().any(|t| t.text == TAND && t.mobile);
+    let can_be_at = |tile: &Tile, pos: Option<usize>| match pos {
+        Some(pos) if tile.mobile => region[pos],
+        Some(pos) => tile.pos == pos,
+        None => false,
+    };
+    let lined_up = |subject: &Tile, is: &Tile, target: &Tile| {
+        let axes: [fn(&Grid, usize) -> Option<usize>; 2] = [Grid::right, Grid::down];
+        (0..region.len()).any(|pos| {
+            can_be_at(subject, Some(pos))
+                && axes.iter().any(|step| {
+                    let is_pos = step(grid, pos);
+                    can_be_at(is, is_pos)
+                        && can_be_at(target, is_pos.and_then(|is_pos| step(grid, is_pos)))
+                })
+        })
+    };
+
+    let mut rules = vec![];
+    for (i, subject) in tiles.iter().enumerate() {
+        let entity = match subject.text {
+            Text::Entity(entity) => entity,
+            _ => continue,
+        };
+        for is in tiles.iter().filter(|t| t.text == TIS) {
+            for (j, target) in tiles.iter().enumerate() {
+                if i == j || target.text == TIS || target.text == TAND {
+                    continue;
+                }
+                let mobile = subject.mobile || is.mobile || target.mobile;
+                if (mobile_and && mobile) || lined_up(subject, is, target) {
+                    rules.push((entity, target.text));
+                }
+            }
+        }
+    }
+    rules
+}
+
+#[test]
+fn finds_the_rules_that_can_form() {
+    use crate::level::*;
+
+    // The rules of the first line are walled off, the other texts can be pushed
+    let mut level = Level::new(7, 5);
+    level.add_rule(&[TBABA, TIS, TYOU], (0, 0), HORIZONTAL);
+    level.add_rule(&[TWALL, TIS, TSTOP], (4, 0), HORIZONTAL);
+    level.add_square_line(Entity::WALL.into(), (0, 1), 7, HORIZONTAL);
+    level.add_square(Entity::BABA.into(), (0, 2));
+    level.add_square(TFLAG.into(), (1, 3));
+    level.add_square(TIS.into(), (3, 3));
+    level.add_square(TWIN.into(), (5, 3));
+
+    let analysis = RuleReachability::analyse(&level);
+    assert!(analysis.permanent[Entity::BABA][usize::from(TYOU)]);
+    assert!(analysis.permanent[Entity::WALL][usize::from(TSTOP)]);
+    assert!(analysis.formable[Entity::FLAG][usize::from(TWIN)]);
+    assert!(!analysis.permanent[Entity::FLAG][usize::from(TWIN)]);
+    assert!(!analysis.formable[Entity::BABA][usize::from(TWIN)]);
+    assert!(analysis.can_win());
+
+    // The texts of level 1 can all be reached
+    let level = crate::levels_list::LEVELS_LIST[0].clone();
+    let analysis = RuleReachability::analyse(&level);
+    assert!(!analysis.permanent[Entity::BABA][usize::from(TYOU)]);
+    assert!(analysis.formable[Entity::ROCK][usize::from(TWIN)]);
+}
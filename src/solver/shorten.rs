@@ -0,0 +1,241 @@
+//! Shortening of the solutions found by the solvers not looking for the shortest one
+//! Three passes are repeated until none of them shortens the solution: cutting the
+//! cycles coming back to a state, replacing the walks of YOU by shortest ones, and
+//! looking for shortcuts between the states along the solution with bounded breadth
+//! first searches. A candidate is only kept if it still wins the level
+
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use crate::interpreter::*;
+use crate::level::Level;
+use crate::solver::macro_moves::*;
+use crate::solver::*;
+
+/// Default number of moves of a local search
+pub const DEFAULT_WINDOW: usize = 8;
+/// Default number of states expanded by a local search
+pub const DEFAULT_LOCAL_NODES: usize = 2_000;
+
+pub struct Shortener {
+    window: usize,
+    max_nodes: usize,
+}
+
+impl Default for Shortener {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Shortener {
+    pub fn new() -> Self {
+        Self {
+            window: DEFAULT_WINDOW,
+            max_nodes: DEFAULT_LOCAL_NODES,
+        }
+    }
+
+    /// Sets the number of moves of the shortcuts searched between two states
+    pub fn with_window(mut self, window: usize) -> Self {
+        self.window = window;
+        self
+    }
+
+    /// Sets the number of states expanded by each local search
+    pub fn with_max_nodes(mut self, max_nodes: usize) -> Self {
+        self.max_nodes = max_nodes;
+        self
+    }
+
+    /// Returns the shortened solution, None if the moves don't win the level.
+    /// The moves played after the first win are dropped
+    pub fn shorten(&self, level: &Level, moves: &[Move]) -> Option<Vec<Move>> {
+        let mut best = moves[..waypoints(level, moves).len() - 1].to_vec();
+        if !wins(level, &best) {
+            return None;
+        }
+        loop {
+            let length = best.len();
+            for candidate in [remove_cycles(level, &best), shorten_walks(level, &best)] {
+                if candidate.len() < best.len() && wins(level, &candidate) {
+                    best = candidate;
+                }
+            }
+            let candidate = self.local_searches(level, &best);
+            if candidate.len() < best.len() && wins(level, &candidate) {
+                best = candidate;
+            }
+            if best.len() == length {
+                return Some(best);
+            }
+        }
+    }
+
+    /// Replaces parts of the solution by the shortcuts found by local searches
+    fn local_searches(&self, level: &Level, moves: &[Move]) -> Vec<Move> {
+        let states = waypoints(level, moves);
+        // State -> last number of moves reaching it along the solution
+        let targets: HashMap<StateKey, usize> = states
+            .iter()
+            .enumerate()
+            .map(|(k, state)| (state_key(state), k))
+            .collect();
+        let mut shortened = vec![];
+        let mut i = 0;
+        while i < moves.len() {
+            match self.shortcut(&states[i], i, &targets, moves.len()) {
+                Some((j, path)) => {
+                    shortened.extend(path);
+                    i = j;
+                }
+                None => {
+                    shortened.push(moves[i]);
+                    i += 1;
+                }
+            }
+        }
+        shortened
+    }
+
+    /// Searches the states reached in a few moves from the state found after `from`
+    /// moves, returns the later state along the solution saving the most moves with
+    /// the path leading to it. Reaching a win leads to the end of the solution
+    fn shortcut(
+        &self,
+        start: &Level,
+        from: usize,
+        targets: &HashMap<StateKey, usize>,
+        end: usize,
+    ) -> Option<(usize, Vec<Move>)> {
+        let mut tree = PathTree::new();
+        let mut visited = HashSet::new();
+        visited.insert(state_key(start));
+        // (node, depth, state)
+        let mut queue = VecDeque::new();
+        queue.push_back((0, 0, start.clone()));
+        let mut expanded = 0;
+        // (saved moves, target, node)
+        let mut best: Option<(usize, usize, usize)> = None;
+        while let Some((node, depth, current)) = queue.pop_front() {
+            if depth == self.window || expanded == self.max_nodes {
+                break;
+            }
+            expanded += 1;
+            for &m in MOVES.iter() {
+                let mut next = current.clone();
+                let game_state = next.apply_move(m);
+                let key = state_key(&next);
+                if !visited.insert(key.clone()) {
+                    continue;
+                }
+                let target = match game_state {
+                    Some(EndState::Win) => Some(end),
+                    Some(EndState::Defeat) => continue,
+                    None => targets.get(&key).cloned(),
+                };
+                let child = tree.push(node, m);
+                if let Some(target) = target.filter(|&target| target > from + depth + 1) {
+                    let saved = target - from - depth - 1;
+                    if best.map_or(true, |(best_saved, _, _)| saved > best_saved) {
+                        best = Some((saved, target, child));
+                    }
+                }
+                if game_state.is_none() {
+                    queue.push_back((child, depth + 1, next));
+                }
+            }
+        }
+        best.map(|(_, target, node)| (target, tree.path(node)))
+    }
+}
+
+/// Returns the states along the moves, the start included, up to the first win
+fn waypoints(level: &Level, moves: &[Move]) -> Vec<Level> {
+    let mut states = vec![level.clone()];
+    for &m in moves {
+        let mut next = states[states.len() - 1].clone();
+        let game_state = next.apply_move(m);
+        states.push(next);
+        if game_state == Some(EndState::Win) {
+            break;
+        }
+    }
+    states
+}
+
+/// Returns if playing the moves wins the level
+fn wins(level: &Level, moves: &[Move]) -> bool {
+    level.clone().apply_move_sequence(moves.to_vec()) == Some(EndState::Win)
+}
+
+/// Cuts the moves between two visits of the same state
+pub fn remove_cycles(level: &Level, moves: &[Move]) -> Vec<Move> {
+    let states = waypoints(level, moves);
+    let mut kept = vec![];
+    // State -> number of kept moves reaching it
+    let mut seen = HashMap::new();
+    seen.insert(state_key(level), 0);
+    for (m, state) in moves.iter().zip(&states[1..]) {
+        kept.push(*m);
+        let key = state_key(state);
+        match seen.get(&key) {
+            Some(&length) => {
+                kept.truncate(length);
+                seen.retain(|_, visit| *visit <= length);
+            }
+            None => {
+                seen.insert(key, kept.len());
+            }
+        }
+    }
+    kept
+}
+
+/// Replaces the moves only walking YOU around by a shortest walk
+pub fn shorten_walks(level: &Level, moves: &[Move]) -> Vec<Move> {
+    let states = waypoints(level, moves);
+    let moves = &moves[..states.len() - 1];
+    let mut shortened = vec![];
+    let mut i = 0;
+    while i < moves.len() {
+        // The walk ends at the last state only differing by the square of YOU
+        let key = normalized_key(&states[i]);
+        let mut j = i;
+        while j < moves.len() && normalized_key(&states[j + 1]) == key {
+            j += 1;
+        }
+        if j == i {
+            shortened.push(moves[i]);
+            i += 1;
+            continue;
+        }
+        let walk = match states[j].units_with_property(TYOU)[..] {
+            [(_, pos)] => walk_to(&states[i], pos),
+            _ => None,
+        };
+        match walk {
+            Some(walk) if walk.len() < j - i => shortened.extend(walk),
+            _ => shortened.extend_from_slice(&moves[i..j]),
+        }
+        i = j;
+    }
+    shortened
+}
+
+#[test]
+fn shortens_a_solution_with_detours() {
+    use crate::notation::parse_moves;
+
+    let level = crate::levels_list::LEVELS_LIST[0].clone();
+    let moves = parse_moves("UDLRR2URDR4LRR").expect("The notation is valid");
+    assert!(wins(&level, &moves));
+
+    let no_cycles = remove_cycles(&level, &moves);
+    assert_eq!(no_cycles.len(), moves.len() - 6);
+    assert!(wins(&level, &no_cycles));
+    assert_eq!(shorten_walks(&level, &[DOWN, RIGHT, UP]), vec![RIGHT]);
+
+    let shortened = Shortener::new().shorten(&level, &moves);
+    assert_eq!(shortened, Some(vec![RIGHT; 8]));
+    assert_eq!(Shortener::new().shorten(&level, &[LEFT]), None);
+}
@@ -0,0 +1,201 @@
+//! Statistics of a search and live progress reports
+//! The statistics are kept in the checkpoints, so a resumed search goes on counting
+
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::solver::limits::SolveOutcome;
+
+/// The parts of a search whose time is measured
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Phase {
+    /// Rebuilding the states and playing the moves
+    Expansion,
+    /// Looking for the new states among the explored ones
+    Duplicates,
+    Heuristic,
+    /// Looking for stuck pushed units in the new states
+    Deadlocks,
+    /// Sorting and writing the runs of the external search
+    Sorting,
+    /// Merging the runs of the external search with the previous layers
+    Merging,
+    Checkpoints,
+}
+
+pub const PHASES: [Phase; 7] = [
+    Phase::Expansion,
+    Phase::Duplicates,
+    Phase::Heuristic,
+    Phase::Deadlocks,
+    Phase::Sorting,
+    Phase::Merging,
+    Phase::Checkpoints,
+];
+
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Phase::Expansion => "expansion",
+            Phase::Duplicates => "duplicates",
+            Phase::Heuristic => "heuristic",
+            Phase::Deadlocks => "deadlocks",
+            Phase::Sorting => "sorting",
+            Phase::Merging => "merging",
+            Phase::Checkpoints => "checkpoints",
+        };
+        f.pad(name)
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct SearchStats {
+    /// Number of states whose successors were generated
+    pub expanded: u64,
+    /// Number of successors generated, including the already explored ones
+    pub generated: u64,
+    /// Number of successors that were already explored
+    pub duplicates: u64,
+    /// Number of new states dropped as deadlocks
+    pub pruned: u64,
+    /// Number of new states reached at each depth, the frontier of a breadth first search
+    pub frontier_by_depth: Vec<u64>,
+    pub elapsed: Duration,
+    /// Time spent in each phase, in the order of `PHASES`
+    pub phases: [Duration; PHASES.len()],
+    /// Largest estimated memory used by the stored states, in bytes
+    pub peak_memory: usize,
+}
+
+impl SearchStats {
+    /// Average number of successors of an expanded state
+    pub fn branching_factor(&self) -> f64 {
+        if self.expanded == 0 {
+            0.0
+        } else {
+            self.generated as f64 / self.expanded as f64
+        }
+    }
+
+    /// Runs a part of the search and adds its duration to the phase
+    pub fn time<T>(&mut self, phase: Phase, run: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let result = run();
+        self.add_time(phase, start);
+        result
+    }
+
+    /// Adds the time elapsed since the start of a part of the search to the phase
+    pub fn add_time(&mut self, phase: Phase, start: Instant) {
+        self.phases[phase as usize] += start.elapsed();
+    }
+
+    /// Records the estimated memory used by the stored states
+    pub fn memory(&mut self, bytes: usize) {
+        self.peak_memory = self.peak_memory.max(bytes);
+    }
+
+    /// Counts a new state reached at the given depth
+    pub fn reached(&mut self, depth: usize) {
+        if self.frontier_by_depth.len() <= depth {
+            self.frontier_by_depth.resize(depth + 1, 0);
+        }
+        self.frontier_by_depth[depth] += 1;
+    }
+
+    /// Adds the statistics of a part of the search run on another thread
+    pub fn merge(&mut self, other: &Self) {
+        self.expanded += other.expanded;
+        self.generated += other.generated;
+        self.duplicates += other.duplicates;
+        self.pruned += other.pruned;
+        if self.frontier_by_depth.len() < other.frontier_by_depth.len() {
+            self.frontier_by_depth
+                .resize(other.frontier_by_depth.len(), 0);
+        }
+        for (total, states) in self
+            .frontier_by_depth
+            .iter_mut()
+            .zip(other.frontier_by_depth.iter())
+        {
+            *total += states;
+        }
+        for (total, time) in self.phases.iter_mut().zip(other.phases.iter()) {
+            *total += *time;
+        }
+        self.peak_memory = self.peak_memory.max(other.peak_memory);
+    }
+}
+
+impl fmt::Display for SearchStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "expanded:         {}", self.expanded)?;
+        writeln!(f, "generated:        {}", self.generated)?;
+        writeln!(f, "duplicates:       {}", self.duplicates)?;
+        writeln!(f, "pruned:           {}", self.pruned)?;
+        writeln!(f, "branching factor: {:.2}", self.branching_factor())?;
+        writeln!(f, "peak memory:      {} KiB", self.peak_memory >> 10)?;
+        writeln!(f, "elapsed:          {:.3}s", self.elapsed.as_secs_f64())?;
+        for (phase, time) in PHASES.iter().zip(self.phases.iter()) {
+            if !time.is_zero() {
+                writeln!(f, "  {:<15} {:.3}s", phase, time.as_secs_f64())?;
+            }
+        }
+        write!(f, "frontier by depth:")?;
+        for states in &self.frontier_by_depth {
+            write!(f, " {}", states)?;
+        }
+        Ok(())
+    }
+}
+
+/// The outcome of a search with its statistics
+#[derive(Clone, Debug)]
+pub struct SolveReport {
+    pub outcome: SolveOutcome,
+    pub stats: SearchStats,
+}
+
+/// A callback receiving the statistics of a running search
+#[derive(Clone)]
+pub struct Progress {
+    /// Minimum time between two calls
+    pub interval: Duration,
+    pub callback: Arc<dyn Fn(&SearchStats) + Send + Sync>,
+}
+
+/// Calls the progress callback of a search when its interval elapsed
+pub struct Reporter {
+    progress: Option<Progress>,
+    last_report: Instant,
+}
+
+impl Reporter {
+    pub fn new(progress: &Option<Progress>) -> Self {
+        Self {
+            progress: progress.clone(),
+            last_report: Instant::now(),
+        }
+    }
+
+    pub fn report(&mut self, stats: &SearchStats) {
+        if let Some(progress) = &self.progress {
+            if self.last_report.elapsed() >= progress.interval {
+                (progress.callback)(stats);
+                self.last_report = Instant::now();
+            }
+        }
+    }
+}
+
+#[test]
+fn counts_every_generated_state() {
+    let level = crate::levels_list::LEVELS_LIST[0].clone();
+    let stats = crate::solver::bfs::Bfs::new(20).solve(&level).stats;
+    let reached: u64 = stats.frontier_by_depth.iter().sum();
+    assert_eq!(stats.frontier_by_depth[0], 1);
+    assert_eq!(stats.frontier_by_depth.len(), 9);
+    assert_eq!(stats.generated - stats.duplicates + 1, reached);
+    assert!(stats.branching_factor() > 0.0 && stats.branching_factor() <= 4.0);
+}
@@ -0,0 +1,146 @@
+//! Symmetries of a level, the states that are the image of each other by a
+//! symmetry are equivalent and only one of them is explored
+//! The rules read left to right and top to bottom, so a symmetry must map these two
+//! directions onto each other: mirrors and rotations make the rules unreadable, only
+//! the transposition along the main diagonal of a square grid keeps them
+//!
+//! The explored states are identified by their canonical key but expanded from the
+//! first concrete state reaching them, so the moves found stay valid on the original board
+
+use crate::interpreter::*;
+use crate::level::Level;
+use crate::solver::*;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Transform {
+    Identity,
+    /// Swaps the rows and the columns, and the horizontal and vertical rules
+    Transpose,
+}
+
+impl Transform {
+    /// Returns the square the given one is mapped to on a grid of the given width
+    pub fn square(self, pos: usize, width: usize) -> usize {
+        match self {
+            Transform::Identity => pos,
+            Transform::Transpose => (pos % width) * width + pos / width,
+        }
+    }
+
+    /// Returns the move played on the transformed board
+    pub fn apply_move(self, m: Move) -> Move {
+        match (self, m) {
+            (Transform::Identity, m) => m,
+            (Transform::Transpose, UP) => LEFT,
+            (Transform::Transpose, LEFT) => UP,
+            (Transform::Transpose, DOWN) => RIGHT,
+            (Transform::Transpose, RIGHT) => DOWN,
+            (Transform::Transpose, m) => m,
+        }
+    }
+
+    /// Returns the key of the transformed state
+    pub fn key(self, level: &Level) -> StateKey {
+        let squares = level.grid.squares();
+        let width = level.grid.width();
+        let mut key = squares.to_vec();
+        for (pos, &square) in squares.iter().enumerate() {
+            key[self.square(pos, width)] = square;
+        }
+        key
+    }
+
+    /// Returns if the transform can be applied on the grid
+    fn fits(self, level: &Level) -> bool {
+        match self {
+            Transform::Identity => true,
+            Transform::Transpose => level.grid.width() == level.grid.height(),
+        }
+    }
+}
+
+/// The transforms keeping a level unchanged, identifying its equivalent states
+#[derive(Clone, Debug)]
+pub struct Symmetries {
+    transforms: Vec<Transform>,
+}
+
+impl Symmetries {
+    /// Detects the symmetries of the level in its current state
+    pub fn new(level: &Level) -> Self {
+        let key = state_key(level);
+        let transforms = [Transform::Identity, Transform::Transpose]
+            .iter()
+            .cloned()
+            .filter(|&t| t.fits(level) && t.key(level) == key)
+            .collect();
+        Self { transforms }
+    }
+
+    /// Only the identity, every state is its own canonical one
+    pub fn none() -> Self {
+        Self {
+            transforms: vec![Transform::Identity],
+        }
+    }
+
+    /// Returns the transforms of the group, the identity included
+    pub fn transforms(&self) -> &[Transform] {
+        &self.transforms
+    }
+
+    /// Returns the smallest key among the images of the state
+    pub fn canonical_key(&self, level: &Level) -> StateKey {
+        if self.transforms.len() == 1 {
+            return state_key(level);
+        }
+        self.transforms
+            .iter()
+            .map(|&t| t.key(level))
+            .min()
+            .expect("The identity is always a symmetry")
+    }
+}
+
+#[test]
+fn explores_a_transposed_state_once() {
+    use crate::level::*;
+    use crate::solver::bfs::Bfs;
+    use crate::square::*;
+
+    // Every rule is written both horizontally and vertically
+    let mut level = Level::new(7, 7);
+    level.add_rule(&[TBABA, TIS, TYOU], (0, 0), HORIZONTAL);
+    level.add_rule(&[TBABA, TIS, TYOU], (0, 0), VERTICAL);
+    level.add_rule(&[TFLAG, TIS, TWIN], (0, 4), HORIZONTAL);
+    level.add_rule(&[TFLAG, TIS, TWIN], (4, 0), VERTICAL);
+    level.add_square(Entity::BABA.into(), (3, 3));
+    level.add_square(Entity::FLAG.into(), (6, 6));
+
+    let symmetries = Symmetries::new(&level);
+    assert_eq!(
+        symmetries.transforms(),
+        &[Transform::Identity, Transform::Transpose]
+    );
+    let (mut down, mut right) = (level.clone(), level.clone());
+    down.apply_move(DOWN);
+    right.apply_move(Transform::Transpose.apply_move(DOWN));
+    assert_eq!(
+        symmetries.canonical_key(&down),
+        symmetries.canonical_key(&right)
+    );
+
+    let report = Bfs::new(20).solve(&level);
+    let plain = Bfs::new(20).without_symmetries().solve(&level);
+    let solution = report.outcome.solution().expect("The flag can be reached");
+    assert_eq!(solution.len(), 6);
+    assert_eq!(
+        level.clone().apply_move_sequence(solution),
+        Some(EndState::Win)
+    );
+    assert!(report.stats.generated < plain.stats.generated);
+
+    // The grid of level 1 isn't square
+    let level = crate::levels_list::LEVELS_LIST[0].clone();
+    assert_eq!(Symmetries::new(&level).transforms(), &[Transform::Identity]);
+}
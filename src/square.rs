@@ -31,6 +31,7 @@ enum_from_primitive! {
         WIN,
         STOP,
         PUSH,
+        DEFEAT,
     }
 }
 
@@ -40,6 +41,7 @@ pub enum Text {
     Entity(Entity),
     Property(Property),
     Is,
+    And,
 }
 
 /// All the individual value a square can take
@@ -49,13 +51,14 @@ pub enum LayeredSquare {
     Text(Text),
 }
 
-/// A square is a boolean table of all the possible layered squares (superposition)
-#[derive(Default, Clone, Copy)]
+/// A square is a boolean table of all the possible layered squares (superposition),
+/// so it holds at most one unit of each kind
+#[derive(Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Square {
     value: u32,
 }
 
-pub const TEXTS_NUMBER: usize = Entity::VARIANT_COUNT + Property::VARIANT_COUNT + 1;
+pub const TEXTS_NUMBER: usize = Entity::VARIANT_COUNT + Property::VARIANT_COUNT + 2;
 pub const LAYERED_SQUARES_NUMBER: usize = Entity::VARIANT_COUNT + TEXTS_NUMBER;
 
 impl Square {
@@ -68,11 +71,27 @@ impl Square {
     pub fn remove_layer(&mut self, layer: LayeredSquare) {
         self.value &= !(1 << usize::from(layer));
     }
+
+    /// Returns if the given layer is present in the square
+    pub fn has_layer(&self, layer: LayeredSquare) -> bool {
+        self.value & (1 << usize::from(layer)) != 0
+    }
+
+    /// Returns the raw boolean table of the square, one bit per layered square
+    pub fn bits(&self) -> u32 {
+        self.value
+    }
+
+    /// Creates a square from its raw boolean table
+    pub fn from_bits(value: u32) -> Self {
+        Self { value }
+    }
 }
 
 // Text shortcut constants
 pub const TBABA: Text = Text::Entity(Entity::BABA);
 pub const TIS: Text = Text::Is;
+pub const TAND: Text = Text::And;
 pub const TYOU: Text = Text::Property(Property::YOU);
 pub const TFLAG: Text = Text::Entity(Entity::FLAG);
 pub const TWIN: Text = Text::Property(Property::WIN);
@@ -80,6 +99,7 @@ pub const TWALL: Text = Text::Entity(Entity::WALL);
 pub const TSTOP: Text = Text::Property(Property::STOP);
 pub const TROCK: Text = Text::Entity(Entity::ROCK);
 pub const TPUSH: Text = Text::Property(Property::PUSH);
+pub const TDEFEAT: Text = Text::Property(Property::DEFEAT);
 
 // Entities list
 pub const ENTITIES: [Entity; Entity::VARIANT_COUNT] = [
@@ -109,7 +129,13 @@ impl From<usize> for LayeredSquare {
                         // We try to convert the index into a property
                         Property::from_usize(index).map_or_else(
                             // If it fails we have a keyword
-                            || LayeredSquare::Text(Text::Is),
+                            || {
+                                if index == Property::VARIANT_COUNT {
+                                    LayeredSquare::Text(Text::Is)
+                                } else {
+                                    LayeredSquare::Text(Text::And)
+                                }
+                            },
                             // If it succeeds we have a text property
                             LayeredSquare::from,
                         )
@@ -141,6 +167,17 @@ impl From<Text> for usize {
             Text::Entity(e) => e as usize,
             Text::Property(p) => Entity::VARIANT_COUNT + p as usize,
             Text::Is => Entity::VARIANT_COUNT + Property::VARIANT_COUNT,
+            Text::And => Entity::VARIANT_COUNT + Property::VARIANT_COUNT + 1,
+        }
+    }
+}
+
+// Converts a text index back to the text
+impl From<usize> for Text {
+    fn from(index: usize) -> Self {
+        match LayeredSquare::from(Entity::VARIANT_COUNT + index) {
+            LayeredSquare::Text(t) => t,
+            LayeredSquare::Entity(_) => unreachable!("A shifted index is always a text"),
         }
     }
 }
@@ -222,6 +259,18 @@ impl Iterator for SquareIterator {
     }
 }
 
+// Displays a text as it is written on its tile
+impl fmt::Display for Text {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Text::Entity(e) => write!(f, "{:?}", e),
+            Text::Property(p) => write!(f, "{:?}", p),
+            Text::Is => write!(f, "IS"),
+            Text::And => write!(f, "AND"),
+        }
+    }
+}
+
 impl fmt::Debug for Square {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         for e in self.into_iter() {